@@ -17,23 +17,43 @@
 #![deny(missing_docs)]
 
 use std::{
+    borrow::Cow,
+    cmp,
+    collections::{HashMap, HashSet},
     error::Error,
     fmt,
-    fmt::{Display, Formatter},
-    fs, io,
+    fmt::{Debug, Display, Formatter},
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem,
     num::ParseIntError,
+    ops::Range,
     path::{Path, PathBuf},
     str::{self, Utf8Error},
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
     time::{Duration, SystemTime},
 };
 
+use hmac::{Hmac, Mac};
 use nelf::{NelfIter, ToCell};
+use sha2::{Digest, Sha256};
 use wgdiff::{
     Deletion, Diff, Difference, OwnedDifference, OwnedInsertion, Patch,
 };
 
 const INIT_VERSION_NAME: &str = "init";
 const DEFAULT_VERSION_NAME: &str = "unnamed";
+const RELOCATION_VERSION_NAME: &str = "relocated";
+const BESIDE_EXTENSION: &str = ".sbvc";
+const BACKUP_EXTENSION: &str = ".bak";
+const INDEX_EXTENSION: &str = ".idx";
+const TEMPORARY_EXTENSION: &str = ".tmp";
+const CACHE_SIZE: usize = 8;
+const CHUNKED_DIFF_THRESHOLD: usize = 1 << 16;
+const CHUNK_MIN: usize = 1 << 10;
+const CHUNK_MAX: usize = 1 << 14;
+const CHUNK_MASK: u64 = 0xfff0_0000_0000_0000;
+const GEAR: [u64; 256] = gear();
 
 /// An enum that represents any error that can occur while using this library.
 #[derive(Debug)]
@@ -47,12 +67,69 @@ pub enum SbvcError {
     InvalidFormat(String),
     /// UTF-8 error.
     Utf8(Utf8Error),
+    /// UTF-8 error in a text field of the version tree file.
+    ///
+    /// Contains a string describing the field, e.g. the name of which version
+    /// is invalid, and the UTF-8 error itself.
+    Utf8Field(String, Utf8Error),
     /// Integer parse error.
     Parse(ParseIntError),
     /// Version not found error.
     ///
     /// Contains the index of the version that was not found.
     VersionNotFound(u32),
+    /// Not an ancestor error.
+    ///
+    /// Occurs when a version was expected to be an ancestor of another one.
+    /// Contains the IDs of the expected ancestor and its expected descendant.
+    NotAncestor(u32, u32),
+    /// Patch failed error.
+    ///
+    /// Occurs when a patch file cannot be parsed or applied. Contains the path
+    /// to the patch file.
+    PatchFailed(PathBuf),
+    /// Too large error.
+    ///
+    /// Occurs when a version tree file exceeds [`Limits`]. Contains a string
+    /// describing the exceeded limit.
+    ///
+    /// [`Limits`]: Limits
+    TooLarge(String),
+    /// Invalid operation error.
+    ///
+    /// Occurs when an operation cannot be performed on the version tree, e.g.
+    /// because it would create a cycle. Contains a string describing the
+    /// error.
+    InvalidOperation(String),
+    /// Self tracking error.
+    ///
+    /// Occurs when the tracked file is the version tree file itself.
+    SelfTracking,
+    /// Not a version tree error.
+    ///
+    /// Occurs when the file opened as a version tree file does not look like
+    /// one, e.g. because it is the tracked file. Contains the path to the file.
+    NotATree(PathBuf),
+    /// Would orphan current error.
+    ///
+    /// Occurs when deleting a version would delete the current version as
+    /// well. Contains the ID of the version to delete.
+    WouldOrphanCurrent(u32),
+    /// Hook rejected error.
+    ///
+    /// Occurs when the pre-commit hook rejects a commit. Contains the string
+    /// returned by the hook.
+    HookRejected(String),
+    /// Name not found error.
+    ///
+    /// Contains the name of the version that was not found.
+    NameNotFound(String),
+    /// Merge conflict error.
+    ///
+    /// Occurs when both sides of a merge changed the same part of contents and
+    /// the conflict is not resolved. Contains the range of contents of their
+    /// common ancestor that was changed.
+    MergeConflict(Range<usize>),
 }
 
 impl From<io::Error> for SbvcError {
@@ -81,29 +158,167 @@ impl Display for SbvcError {
                 write!(f, "SBVC Invalid Format Error: {}", error)
             }
             SbvcError::Utf8(error) => write!(f, "SBVC UTF-8 Error: {}", error),
+            SbvcError::Utf8Field(field, error) => {
+                write!(f, "SBVC UTF-8 Error in {}: {}", field, error)
+            }
             SbvcError::Parse(error) => write!(f, "SBVC Parse Error: {}", error),
             SbvcError::VersionNotFound(id) => {
                 write!(f, "SBVC Error: Version with ID {} not nound", id)
             }
+            SbvcError::NotAncestor(ancestor, id) => write!(
+                f,
+                "SBVC Error: Version with ID {} is not an ancestor of version \
+                with ID {}",
+                ancestor, id
+            ),
+            SbvcError::PatchFailed(path) => write!(
+                f,
+                "SBVC Error: Patch {} cannot be applied",
+                path.display()
+            ),
+            SbvcError::TooLarge(error) => {
+                write!(f, "SBVC Too Large Error: {}", error)
+            }
+            SbvcError::InvalidOperation(error) => {
+                write!(f, "SBVC Invalid Operation Error: {}", error)
+            }
+            SbvcError::SelfTracking => {
+                write!(f, "SBVC Error: Version tree file cannot track itself")
+            }
+            SbvcError::NotATree(path) => write!(
+                f,
+                "SBVC Error: {} is not a version tree file",
+                path.display()
+            ),
+            SbvcError::WouldOrphanCurrent(id) => write!(
+                f,
+                "SBVC Error: Deleting version with ID {} would delete the \
+                current version",
+                id
+            ),
+            SbvcError::HookRejected(error) => {
+                write!(f, "SBVC Hook Rejected Error: {}", error)
+            }
+            SbvcError::NameNotFound(name) => {
+                write!(f, "SBVC Error: Version with name {:?} not found", name)
+            }
+            SbvcError::MergeConflict(range) => write!(
+                f,
+                "SBVC Merge Conflict Error: Both versions changed bytes {:?} \
+                of their common ancestor",
+                range
+            ),
         }
     }
 }
 
-impl Error for SbvcError {}
+impl Error for SbvcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SbvcError::Io(error) => Some(error),
+            SbvcError::Utf8(error) | SbvcError::Utf8Field(_, error) => {
+                Some(error)
+            }
+            SbvcError::Parse(error) => Some(error),
+            _ => None,
+        }
+    }
+}
 
 /// The [`Result`] type for this crate.
 ///
 /// [`Result`]: Result
 pub type SbvcResult<T> = Result<T, SbvcError>;
 
+/// Limits enforced while parsing a version tree file.
+///
+/// Used by [`Sbvc::open_with_limits`]. The default limits are generous enough
+/// for normal use.
+///
+/// [`Sbvc::open_with_limits`]: Sbvc::open_with_limits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of versions in the version tree.
+    pub max_versions: usize,
+    /// Maximum size of the version tree file in bytes.
+    pub max_file_size: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { max_versions: 1 << 24, max_file_size: 1 << 36 }
+    }
+}
+
+/// A repair applied automatically while opening a version tree file.
+///
+/// Returned by [`Sbvc::open_lenient`].
+///
+/// [`Sbvc::open_lenient`]: Sbvc::open_lenient
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The list of versions was missing or empty, so only the initial version
+    /// was created.
+    VersionsMissing,
+    /// The current version was not found, so the initial version was checked
+    /// out instead.
+    ///
+    /// Contains the stored ID of the current version.
+    CurrentReset(u32),
+    /// The stored ID for the next version was not greater than IDs of all
+    /// versions.
+    ///
+    /// Contains the stored ID and the ID it was replaced with.
+    NextFixed(u32, u32),
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Warning::VersionsMissing => write!(
+                f,
+                "SBVC Warning: List of versions is missing, only the initial \
+                version is restored"
+            ),
+            Warning::CurrentReset(id) => write!(
+                f,
+                "SBVC Warning: Current version with ID {} not found, the \
+                initial version is checked out",
+                id
+            ),
+            Warning::NextFixed(stored, fixed) => write!(
+                f,
+                "SBVC Warning: Next version ID {} is already used, replaced \
+                with {}",
+                stored, fixed
+            ),
+        }
+    }
+}
+
 /// A struct that represents the file where the version tree is contained.
 #[derive(Debug, Clone)]
 pub struct Sbvc {
     path: PathBuf,
     file: PathBuf,
+    // Index of the current version in `versions`. Every method that removes
+    // versions keeps it in bounds
     current: usize,
     next: u32,
     versions: Vec<Version>,
+    default_rollback: bool,
+    coalesce_window: Option<Duration>,
+    coalesce_updates_date: bool,
+    deleted: Option<Deleted>,
+    signing_key: Option<Vec<u8>>,
+    working: Option<Vec<u8>>,
+    source_hash: Option<String>,
+    durable: bool,
+    compression: bool,
+    deduplication: bool,
+    indexed: bool,
+    cache: Cache,
+    pre_commit_hook: Hook,
 }
 
 impl Sbvc {
@@ -111,414 +326,5231 @@ impl Sbvc {
     ///
     /// # Errors
     ///
-    /// This method will return an error if an IO error occurs.
+    /// This method will return an error if an IO error occurs or if `file` is
+    /// the same file as `path`.
     ///
     /// [`Sbvc`]: Sbvc
     pub fn new(path: PathBuf, file: PathBuf) -> SbvcResult<Self> {
+        check_self_tracking(&path, &file)?;
+
         let sbvc = Sbvc {
             path,
             file,
             current: 0,
             next: 1,
-            versions: vec![Version {
-                id: 0,
-                base: 0,
-                name: INIT_VERSION_NAME.to_string(),
-                date: SystemTime::now(),
-                difference: OwnedDifference::empty(),
-            }],
+            versions: vec![Version::init()],
+            default_rollback: false,
+            coalesce_window: None,
+            coalesce_updates_date: false,
+            deleted: None,
+            signing_key: None,
+            working: None,
+            source_hash: None,
+            durable: false,
+            compression: false,
+            deduplication: false,
+            indexed: false,
+            cache: Cache::default(),
+            pre_commit_hook: Hook::default(),
         };
         sbvc.write()?;
         Ok(sbvc)
     }
 
+    /// Same as [`new`], but if the tracked file exists and is not empty, also
+    /// commits its contents, so that the first version after the initial one
+    /// captures them.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if an IO error occurs.
+    ///
+    /// [`new`]: Sbvc::new
+    pub fn new_with_initial_commit(
+        path: PathBuf,
+        file: PathBuf,
+    ) -> SbvcResult<Self> {
+        let mut sbvc = Sbvc::new(path, file)?;
+
+        match fs::metadata(&sbvc.file) {
+            Ok(metadata) if metadata.len() > 0 => sbvc.commit()?,
+            _ => (),
+        }
+
+        Ok(sbvc)
+    }
+
+    /// Same as [`new`], but stores the version tree file next to the tracked
+    /// file, at its path with `.sbvc` appended.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if an IO error occurs.
+    ///
+    /// [`new`]: Sbvc::new
+    pub fn new_beside(file: PathBuf) -> SbvcResult<Self> {
+        Sbvc::new(with_suffix(&file, BESIDE_EXTENSION), file)
+    }
+
+    /// Same as [`open`], but opens the version tree file stored next to the
+    /// tracked file, at its path with `.sbvc` appended.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if an IO error or parsing error occurs.
+    ///
+    /// [`open`]: Sbvc::open
+    pub fn open_beside(file: PathBuf) -> SbvcResult<Self> {
+        Sbvc::open(with_suffix(&file, BESIDE_EXTENSION))
+    }
+
     /// Constructs a [`Sbvc`] instance from path to file containing version
     /// tree of a file.
     ///
+    /// If the stored ID for the next version is not greater than IDs of all
+    /// versions, it is fixed, so that new versions always get unique IDs.
+    ///
     /// # Errors
     ///
-    /// This method will return an error if an IO error or parsing error occurs.
+    /// This method will return an error if an IO error or parsing error occurs,
+    /// including when a base version does not exist or bases of versions form
+    /// a cycle. If the file does not look like a version tree file at all,
+    /// [`SbvcError::NotATree`] is returned.
     ///
     /// [`Sbvc`]: Sbvc
     pub fn open(path: PathBuf) -> SbvcResult<Self> {
-        let source = fs::read(&path)?;
-        let mut iter = NelfIter::from_string(&source);
+        Sbvc::open_private(path, None, false, &Limits::default())
+    }
 
-        let file = str::from_utf8(iter.next().ok_or_else(|| {
-            SbvcError::InvalidFormat("Expected filename".to_string())
-        })?)?
-        .into();
+    /// Same as [`open`], but recovers from a version tree file that refers to
+    /// a current version that does not exist by checking out the initial
+    /// version instead.
+    ///
+    /// A version tree file that is missing the list of versions or has an
+    /// empty one, e.g. because writing it was interrupted, is treated as
+    /// containing only the initial version.
+    ///
+    /// Every such repair, as well as fixing the stored ID for the next
+    /// version, is described by a [`Warning`] in the returned list, so that
+    /// the user can be informed about it.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if an IO error or parsing error occurs.
+    ///
+    /// [`open`]: Sbvc::open
+    /// [`Warning`]: Warning
+    pub fn open_lenient(path: PathBuf) -> SbvcResult<(Self, Vec<Warning>)> {
+        let mut warnings = Vec::new();
+        let sbvc = Sbvc::open_private(
+            path,
+            Some(&mut warnings),
+            false,
+            &Limits::default(),
+        )?;
+        Ok((sbvc, warnings))
+    }
 
-        let current_id = str::from_utf8(iter.next().ok_or_else(|| {
-            SbvcError::InvalidFormat("Expected current version ID".to_string())
-        })?)?
-        .parse()?;
+    /// Same as [`open`], but enforces the specified limits while parsing, so
+    /// that untrusted version tree files can be opened defensively.
+    ///
+    /// [`open`] enforces default limits.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if an IO error or parsing error occurs,
+    /// or if the version tree file exceeds the limits.
+    ///
+    /// [`open`]: Sbvc::open
+    pub fn open_with_limits(
+        path: PathBuf,
+        limits: &Limits,
+    ) -> SbvcResult<Self> {
+        Sbvc::open_private(path, None, false, limits)
+    }
 
-        let next = str::from_utf8(iter.next().ok_or_else(|| {
-            SbvcError::InvalidFormat("Expected next version ID".to_string())
-        })?)?
-        .parse()?;
+    /// Same as [`open`], but reads the version tree from `reader` instead of
+    /// the file at `path`.
+    ///
+    /// The version tree is still saved to `path` by methods that modify it. If
+    /// `file` is `Some`, it replaces the tracked file stored in the version
+    /// tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if an IO error or parsing error occurs.
+    ///
+    /// [`open`]: Sbvc::open
+    pub fn open_from(
+        reader: impl Read,
+        path: PathBuf,
+        file: Option<PathBuf>,
+    ) -> SbvcResult<Self> {
+        Sbvc::open_private_from(
+            reader,
+            path,
+            file,
+            false,
+            None,
+            false,
+            &Limits::default(),
+        )
+    }
 
-        let mut versions = Vec::new();
+    /// Same as [`open_from`], but the tracked file is always replaced with
+    /// `file`, so that a version tree written by [`write_to`] can be read
+    /// regardless of where it came from.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if an IO error or parsing error occurs.
+    ///
+    /// [`open_from`]: Sbvc::open_from
+    /// [`write_to`]: Sbvc::write_to
+    pub fn read_from<R: Read>(
+        reader: R,
+        path: PathBuf,
+        file: PathBuf,
+    ) -> SbvcResult<Self> {
+        Sbvc::open_from(reader, path, Some(file))
+    }
 
-        for version in NelfIter::from_string(iter.next().ok_or_else(|| {
-            SbvcError::InvalidFormat("Expected list of versions".to_string())
-        })?)
-        .map(|source| -> SbvcResult<Version> {
-            let mut iter = NelfIter::from_string(source);
+    /// Same as [`open`], but loads only metadata of versions, such as their
+    /// names, dates and sizes, keeping contents of the version tree file in
+    /// memory to load inserted bytes of each version when they are first
+    /// needed. Version tree files with compressed insertions are loaded
+    /// entirely.
+    ///
+    /// If the version tree file has an index (see [`set_indexed`]), inserted
+    /// bytes are not read until they are needed, and reading them panics if
+    /// the file became unreadable in the meantime.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if an IO error or parsing error occurs.
+    ///
+    /// [`open`]: Sbvc::open
+    /// [`set_indexed`]: Sbvc::set_indexed
+    pub fn open_lazy(path: PathBuf) -> SbvcResult<Self> {
+        Sbvc::open_private(path, None, true, &Limits::default())
+    }
 
-            let id = str::from_utf8(iter.next().ok_or_else(|| {
-                SbvcError::InvalidFormat("Expected version id".to_string())
-            })?)?
-            .parse()?;
+    fn open_private(
+        path: PathBuf,
+        warnings: Option<&mut Vec<Warning>>,
+        lazy: bool,
+        limits: &Limits,
+    ) -> SbvcResult<Self> {
+        let mut reader = fs::File::open(&path)?;
+        let index = fs::read(with_suffix(&path, INDEX_EXTENSION)).ok();
 
-            let base = str::from_utf8(iter.next().ok_or_else(|| {
-                SbvcError::InvalidFormat("Expected base version id".to_string())
-            })?)?
-            .parse()?;
+        // The index is only used if the version tree file was not changed
+        // since it was written. If opening through it fails anyway, the file
+        // is read as if there was no index
+        if let Some(index) = index.as_deref().and_then(|index| {
+            let metadata = reader.metadata().ok()?;
+            parse_index(index, metadata.len(), metadata.modified().ok()?)
+        }) {
+            let mut indexed_warnings = Vec::new();
 
-            let mut meta =
-                NelfIter::from_string(iter.next().ok_or_else(|| {
-                    SbvcError::InvalidFormat(
-                        "Expected version metadata".to_string(),
-                    )
-                })?);
+            if let Ok(sbvc) = Sbvc::open_indexed(
+                reader,
+                path.clone(),
+                index,
+                warnings.is_some().then_some(&mut indexed_warnings),
+                lazy,
+                limits,
+            ) {
+                if let Some(warnings) = warnings {
+                    warnings.append(&mut indexed_warnings);
+                }
 
-            let name = str::from_utf8(meta.next().ok_or_else(|| {
-                SbvcError::InvalidFormat("Expected version name".to_string())
-            })?)?
-            .to_string();
+                return Ok(sbvc);
+            }
 
-            let date = SystemTime::UNIX_EPOCH
-                + Duration::from_secs(
-                    str::from_utf8(meta.next().ok_or_else(|| {
-                        SbvcError::InvalidFormat(
-                            "Expected version creation date".to_string(),
-                        )
-                    })?)?
-                    .parse()?,
-                );
+            reader = fs::File::open(&path)?;
+        }
 
-            let mut difference = OwnedDifference::empty();
+        Sbvc::open_private_from(
+            reader,
+            path,
+            None,
+            index.is_some(),
+            warnings,
+            lazy,
+            limits,
+        )
+    }
 
-            for deletion in
-                NelfIter::from_string(iter.next().ok_or_else(|| {
-                    SbvcError::InvalidFormat(
-                        "Expected version deletions".to_string(),
-                    )
-                })?)
-                .map(|source| -> SbvcResult<Deletion> {
-                    let mut iter = NelfIter::from_string(source);
-
-                    let start: usize =
-                        str::from_utf8(iter.next().ok_or_else(|| {
-                            SbvcError::InvalidFormat(
-                                "Expected deletion start".to_string(),
-                            )
-                        })?)?
-                        .parse()?;
-
-                    let end: usize =
-                        str::from_utf8(iter.next().ok_or_else(|| {
-                            SbvcError::InvalidFormat(
-                                "Expected deletion end".to_string(),
-                            )
-                        })?)?
-                        .parse()?;
-
-                    Ok(Deletion { start, end })
-                })
-            {
-                difference.deletions.push(deletion?);
-            }
+    // Reads only the beginning of the version tree file and the parts of it
+    // the index points to. Inserted bytes are read when they are first needed
+    // if `lazy` is `true` and they are not compressed
+    fn open_indexed(
+        mut reader: impl Read + Seek + Send + 'static,
+        path: PathBuf,
+        index: Index,
+        warnings: Option<&mut Vec<Warning>>,
+        lazy: bool,
+        limits: &Limits,
+    ) -> SbvcResult<Self> {
+        if index.len > limits.max_file_size {
+            return Err(SbvcError::TooLarge(format!(
+                "Version tree file is larger than {} bytes",
+                limits.max_file_size
+            )));
+        }
 
-            for insertion in
-                NelfIter::from_string(iter.next().ok_or_else(|| {
-                    SbvcError::InvalidFormat(
-                        "Expected version insertions".to_string(),
-                    )
-                })?)
-                .map(
-                    |source| -> SbvcResult<OwnedInsertion<u8>> {
-                        let mut iter = NelfIter::from_string(source);
-
-                        let start: usize =
-                            str::from_utf8(iter.next().ok_or_else(|| {
-                                SbvcError::InvalidFormat(
-                                    "Expected insertion start".to_string(),
-                                )
-                            })?)?
-                            .parse()?;
-
-                        let data = iter
-                            .next()
-                            .ok_or_else(|| {
-                                SbvcError::InvalidFormat(
-                                    "Expected insertion data".to_string(),
-                                )
-                            })?
-                            .to_vec();
-
-                        Ok(OwnedInsertion { start, data })
-                    },
-                )
-            {
-                difference.insertions.push(insertion?);
-            }
+        let mut start = vec![0; index.header_len];
+        reader.read_exact(&mut start)?;
 
-            Ok(Version { id, base, name, date, difference })
-        }) {
-            versions.push(version?);
+        if hash(&start) != index.header_hash {
+            return Err(SbvcError::InvalidFormat(
+                "Index does not match the version tree file".to_string(),
+            ));
         }
 
-        let current = versions
-            .iter()
-            .enumerate()
-            .find(|&(_, version)| version.id == current_id)
-            .map(|(index, _)| index)
-            .ok_or(SbvcError::VersionNotFound(current_id))?;
+        let (file, current_id, next) =
+            parse_header_start(&mut NelfIter::from_string(&start))?;
+        let compression = index.compression;
 
-        Ok(Sbvc { path, file, current, next, versions })
-    }
+        if compression && !cfg!(feature = "compression") {
+            return Err(SbvcError::InvalidFormat(
+                "Insertions are compressed, which requires the `compression` \
+                feature"
+                    .to_string(),
+            ));
+        }
 
-    fn write(&self) -> SbvcResult<()> {
-        fs::write(
-            &self.path,
-            [
-                self.file.as_os_str().to_str().unwrap().as_bytes(),
-                self.versions[self.current].id.to_string().as_bytes(),
-                self.next.to_string().as_bytes(),
-                &self
-                    .versions
-                    .iter()
-                    .map(|version| {
-                        [
-                            version.id.to_string().as_bytes(),
-                            version.base.to_string().as_bytes(),
-                            &[
-                                version.name.as_bytes(),
-                                version
-                                    .date
-                                    .duration_since(SystemTime::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs()
-                                    .to_string()
-                                    .as_bytes(),
-                            ]
-                            .to_newline_nelf(),
-                            &version
-                                .difference
-                                .deletions
-                                .iter()
-                                .map(|deletion| {
-                                    [
-                                        deletion.start.to_string().as_bytes(),
-                                        deletion.end.to_string().as_bytes(),
-                                    ]
-                                    .to_newline_nelf()
-                                })
-                                .to_newline_nelf(),
-                            &version
-                                .difference
-                                .insertions
-                                .iter()
-                                .map(|insertion| {
-                                    [
-                                        insertion.start.to_string().as_bytes(),
-                                        &insertion.data,
-                                    ]
-                                    .to_newline_nelf()
-                                })
-                                .to_newline_nelf(),
-                        ]
-                        .to_newline_nelf()
-                    })
-                    .to_newline_nelf(),
-            ]
-            .to_newline_nelf(),
+        let reader: SharedReader = Arc::new(Mutex::new(Box::new(reader)));
+        let versions = parse_versions(
+            NelfIter::from_string(&index.versions),
+            Payload::Indexed(&reader, lazy && !compression, compression),
+            None,
+            limits,
         )?;
 
-        Ok(())
+        Sbvc::from_parsed(
+            path,
+            Parsed {
+                file,
+                current_id,
+                next,
+                versions,
+                source_hash: index.source_hash,
+                compression,
+                deduplication: index.deduplication,
+            },
+            true,
+            warnings,
+        )
     }
 
-    fn data(&self, version: &Version) -> Vec<u8> {
-        if version.id != version.base {
-            let mut result =
-                self.data(&self.versions[self.version(version.base).unwrap()]);
-            result.patch(version.difference());
-            result
-        } else {
-            Vec::new()
+    fn open_private_from(
+        reader: impl Read,
+        path: PathBuf,
+        file: Option<PathBuf>,
+        indexed: bool,
+        warnings: Option<&mut Vec<Warning>>,
+        lazy: bool,
+        limits: &Limits,
+    ) -> SbvcResult<Self> {
+        let lenient = warnings.is_some();
+        let mut buffer = Vec::new();
+        reader
+            .take(limits.max_file_size.saturating_add(1))
+            .read_to_end(&mut buffer)?;
+
+        if buffer.len() as u64 > limits.max_file_size {
+            return Err(SbvcError::TooLarge(format!(
+                "Version tree file is larger than {} bytes",
+                limits.max_file_size
+            )));
         }
-    }
 
-    fn rollback(&self) -> SbvcResult<()> {
-        fs::write(&self.file, self.data(&self.versions[self.current]))?;
-        Ok(())
-    }
+        // A file without a readable header is most likely not a version tree
+        // file at all, e.g. the tracked file passed by mistake
+        let compression = match parse_header(&buffer, lenient) {
+            Ok(header) => header.compression,
+            Err(_) if parse_header(&buffer, true).is_err() => {
+                return Err(SbvcError::NotATree(path));
+            }
+            Err(error) => return Err(error),
+        };
 
-    /// Returns `true` if the traced file contents are not the same as the
-    /// content for the current version.
-    pub fn is_changed(&self) -> SbvcResult<bool> {
-        Ok(fs::read(&self.file)? != self.data(&self.versions[self.current]))
+        // Such a version tree could be read if it has no insertions, but could
+        // not be saved again
+        if compression && !cfg!(feature = "compression") {
+            return Err(SbvcError::InvalidFormat(
+                "Insertions are compressed, which requires the `compression` \
+                feature"
+                    .to_string(),
+            ));
+        }
+
+        // Converting the buffer into a shared one copies it, which is only
+        // worth it if versions keep referring to it. Compressed insertions
+        // have to be decompressed anyway, so they are never loaded lazily
+        let source_hash = hash(&buffer);
+        let shared: Arc<[u8]>;
+        let (source, payload) = if compression {
+            (&buffer[..], Payload::Inflate)
+        } else if lazy {
+            shared = buffer.into();
+            (&shared[..], Payload::Lazy(&shared))
+        } else {
+            (&buffer[..], Payload::Copy)
+        };
+
+        let header = parse_header(source, lenient)?;
+        let blobs = header.blobs.map(parse_blobs).transpose()?;
+        let versions = parse_versions(
+            NelfIter::from_string(header.list),
+            payload,
+            blobs.as_ref(),
+            limits,
+        )?;
+
+        Sbvc::from_parsed(
+            path,
+            Parsed {
+                file: file.unwrap_or(header.file),
+                current_id: header.current_id,
+                next: header.next,
+                versions,
+                source_hash,
+                compression,
+                deduplication: blobs.is_some(),
+            },
+            indexed,
+            warnings,
+        )
     }
 
-    /// Switches to the specified version using its ID.
-    ///
-    /// `rollback` specifies whether the contents of the file should be changed
-    /// according to the version you are switching to. If `true`, file contents
-    /// will be changed.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if an IO error happens or the supplied `id` is not
-    /// found in the version tree. If `rollback` is `false` never fails.
-    pub fn checkout(&mut self, id: u32, rollback: bool) -> SbvcResult<()> {
-        self.current =
-            self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+    fn from_parsed(
+        path: PathBuf,
+        parsed: Parsed,
+        indexed: bool,
+        mut warnings: Option<&mut Vec<Warning>>,
+    ) -> SbvcResult<Self> {
+        let Parsed { current_id, mut next, mut versions, .. } = parsed;
+        let lenient = warnings.is_some();
+        let mut warn = |warning| {
+            if let Some(warnings) = &mut warnings {
+                warnings.push(warning);
+            }
+        };
 
-        if rollback {
-            self.rollback()?;
+        if lenient && versions.is_empty() {
+            versions.push(Version::init());
+            warn(Warning::VersionsMissing);
         }
 
-        Ok(())
+        check_bases(
+            &versions
+                .iter()
+                .map(|version| (version.id, version.base))
+                .collect::<Vec<_>>(),
+        )?;
+        check_bounds(&versions)?;
+
+        let current = match versions
+            .iter()
+            .position(|version| version.id == current_id)
+        {
+            Some(current) => current,
+            None if lenient => {
+                let current = versions
+                    .iter()
+                    .position(|version| version.id == version.base)
+                    .ok_or(SbvcError::VersionNotFound(current_id))?;
+                warn(Warning::CurrentReset(current_id));
+                current
+            }
+            None => return Err(SbvcError::VersionNotFound(current_id)),
+        };
+
+        if let Some(max) = versions.iter().map(|version| version.id).max() {
+            if next <= max {
+                let fixed = max.checked_add(1).ok_or_else(|| {
+                    SbvcError::InvalidFormat(format!(
+                        "No version IDs are left after version with ID {}",
+                        max
+                    ))
+                })?;
+                warn(Warning::NextFixed(next, fixed));
+                next = fixed;
+            }
+        }
+
+        Ok(Sbvc {
+            path,
+            file: parsed.file,
+            current,
+            next,
+            versions,
+            default_rollback: false,
+            coalesce_window: None,
+            coalesce_updates_date: false,
+            deleted: None,
+            signing_key: None,
+            working: None,
+            source_hash: Some(parsed.source_hash),
+            durable: false,
+            compression: parsed.compression,
+            deduplication: parsed.deduplication,
+            indexed,
+            cache: Cache::default(),
+            pre_commit_hook: Hook::default(),
+        })
     }
 
-    /// Saves changes in the file to a new version branching from the current
-    /// one.
+    /// Reads the version tree file again and, unless its contents are the same
+    /// as when it was last read by this instance, replaces the version tree
+    /// with the one parsed from it as by [`open`]. Returns `true` if the
+    /// version tree was replaced.
     ///
-    /// This method automatically checks out the newly created version.
+    /// Settings of this instance, such as the default rollback policy, are
+    /// kept. Changes saved by this instance itself also count as changes of
+    /// the file.
     ///
     /// # Errors
     ///
-    /// This method fails if an IO error occurs.
-    pub fn commit(&mut self) -> SbvcResult<()> {
-        let content = fs::read(&self.file)?;
+    /// This method will return an error if an IO error or parsing error occurs,
+    /// in which case the version tree is left unchanged.
+    ///
+    /// [`open`]: Sbvc::open
+    pub fn reload_if_changed(&mut self) -> SbvcResult<bool> {
+        let source = fs::read(&self.path)?;
 
-        self.versions.push(Version {
-            id: self.next,
-            base: self.versions[self.current].id,
-            name: DEFAULT_VERSION_NAME.to_string(),
-            date: SystemTime::now(),
-            // TODO Optimize for big files
-            difference: content
-                .diff(&self.data(&self.versions[self.current]))
-                .to_owned(),
-        });
-        self.next += 1;
-        self.current = self.versions.len() - 1;
-        self.write()
-    }
+        if self.source_hash.as_deref() == Some(&hash(&source)) {
+            return Ok(false);
+        }
 
-    /// Renames the current version.
-    ///
-    /// # Errors
-    ///
-    /// This method returns an error when an IO error occurs.
-    pub fn rename(&mut self, name: &str) -> SbvcResult<()> {
-        self.versions[self.current].name.clear();
-        self.versions[self.current].name.push_str(name);
-        self.write()
+        let reloaded = Sbvc::open_private_from(
+            &source[..],
+            self.path.clone(),
+            None,
+            false,
+            None,
+            false,
+            &Limits::default(),
+        )?;
+        self.file = reloaded.file;
+        self.current = reloaded.current;
+        self.next = reloaded.next;
+        self.versions = reloaded.versions;
+        self.cache = Cache::default();
+        self.deleted = None;
+        self.working = None;
+        self.source_hash = reloaded.source_hash;
+        self.compression = reloaded.compression;
+        self.deduplication = reloaded.deduplication;
+        Ok(true)
     }
 
-    /// Deletes version with the selected ID.
+    /// Checks the structure of the version tree file without constructing a
+    /// [`Sbvc`] instance.
     ///
-    /// This method does not delete the initial version.
+    /// Unlike [`open`], this method reads the file as a stream and never
+    /// holds contents of insertions in memory, so it can check version trees
+    /// of any size. It checks that every part of the file is present and
+    /// well-formed, that version IDs are unique, that the current version and
+    /// bases of all versions exist, and that bases do not form cycles.
     ///
     /// # Errors
     ///
-    /// This method returns an error when and IO error occurs.
-    pub fn delete(&mut self) -> SbvcResult<()> {
-        let current = self.current;
-        self.checkout(self.versions[self.current].base, true)?;
-        self.delete_private(current);
-        self.write()
-    }
-
-    fn delete_private(&mut self, index: usize) {
-        let id = self.versions[index].id;
+    /// Returns an error if an IO error or parsing error occurs, or
+    /// [`SbvcError::InvalidFormat`] if any of the checks fails.
+    ///
+    /// [`Sbvc`]: Sbvc
+    /// [`open`]: Sbvc::open
+    /// [`SbvcError::InvalidFormat`]: SbvcError::InvalidFormat
+    pub fn verify_file(path: &Path) -> SbvcResult<()> {
+        let mut bytes = io::BufReader::new(fs::File::open(path)?).bytes();
+        let mut start = Vec::new();
 
-        if id != self.versions[index].base {
-            while let Some(index) = self
-                .versions
-                .iter()
-                .enumerate()
-                .find(|&(_, version)| version.base == id)
-                .map(|(index, _)| index)
-            {
-                self.delete_private(index);
+        while start.len() < 3 {
+            match next_cell(&mut bytes)? {
+                Some(cell) => start.push(cell.collect::<io::Result<_>>()?),
+                None => break,
             }
-
-            self.versions.remove(self.version(id).unwrap());
         }
+
+        let (_, current_id, _) =
+            parse_header_start(&mut start.iter().map(Vec::as_slice))?;
+        let mut bases = Vec::new();
+        let mut ids = HashSet::new();
+        // Hashes of inserted bytes, which are blob keys if the file is
+        // deduplicated, with IDs of versions inserting them
+        let mut hashes = Vec::new();
+
+        let mut list = next_cell(&mut bytes)?.ok_or_else(|| {
+            SbvcError::InvalidFormat("Expected list of versions".to_string())
+        })?;
+
+        while let Some(cell) = next_cell(&mut list)? {
+            let (version, inserted) = skim_version(cell)?;
+
+            if !ids.insert(version.id) {
+                return Err(SbvcError::InvalidFormat(format!(
+                    "Duplicate version ID {}",
+                    version.id
+                )));
+            }
+
+            bases.push((version.id, version.base));
+            hashes.extend(inserted.into_iter().map(|hash| (hash, version.id)));
+        }
+
+        // Skip the compression flag
+        if let Some(cell) = next_cell(&mut bytes)? {
+            skip_cell(cell)?;
+        }
+
+        if let Some(mut blobs) = next_cell(&mut bytes)? {
+            let mut keys = HashSet::new();
+
+            while let Some(mut blob) = next_cell(&mut blobs)? {
+                let key = next_cell(&mut blob)?.ok_or_else(|| {
+                    SbvcError::InvalidFormat("Expected blob key".to_string())
+                })?;
+                keys.insert(hash_cell(key)?);
+                let data = next_cell(&mut blob)?.ok_or_else(|| {
+                    SbvcError::InvalidFormat("Expected blob data".to_string())
+                })?;
+                skip_cell(data)?;
+                skip_cell(blob)?;
+            }
+
+            if let Some((_, id)) =
+                hashes.iter().find(|(hash, _)| !keys.contains(hash))
+            {
+                return Err(SbvcError::InvalidFormat(format!(
+                    "Blob of version {} not found",
+                    id
+                )));
+            }
+        }
+
+        if !ids.contains(&current_id) {
+            return Err(SbvcError::InvalidFormat(format!(
+                "Current version with ID {} not found",
+                current_id
+            )));
+        }
+
+        check_bases(&bases)
+    }
+
+    /// Reads the file at `path` and returns every version record that can be
+    /// parsed from it, in the order they appear, even if the rest of the file
+    /// is damaged, so that individual versions can be salvaged.
+    ///
+    /// The file is never modified. Records with the ID of an earlier record
+    /// are skipped. Bases of returned versions may not be among them, in
+    /// which case contents of those versions cannot be reconstructed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs.
+    pub fn scan_versions(path: &Path) -> SbvcResult<Vec<Version>> {
+        let source = fs::read(path)?;
+        let mut versions: Vec<Version> = Vec::new();
+
+        // Insertions can only be read if the header survived
+        let header = parse_header(&source, true).ok();
+        let payload = match &header {
+            Some(header) if header.compression => Payload::Inflate,
+            _ => Payload::Copy,
+        };
+        let blobs = header
+            .and_then(|header| header.blobs)
+            .and_then(|blobs| parse_blobs(blobs).ok());
+
+        // Any cell of the file could be the list of versions, or a version
+        // that ended up outside of it
+        for cell in NelfIter::from_string(&source) {
+            for source in [cell].into_iter().chain(NelfIter::from_string(cell))
+            {
+                if let Ok(version) =
+                    Version::from_nelf_private(source, payload, blobs.as_ref())
+                {
+                    if versions.iter().all(|other| other.id != version.id) {
+                        versions.push(version);
+                    }
+                }
+            }
+        }
+
+        Ok(versions)
+    }
+
+    /// Writes the version tree to `writer` in the format of the version tree
+    /// file, e.g. to store it somewhere other than a file. It can be read back
+    /// with [`read_from`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs.
+    ///
+    /// [`read_from`]: Sbvc::read_from
+    pub fn write_to<W: Write>(&self, mut writer: W) -> SbvcResult<()> {
+        writer.write_all(&self.serialize())?;
+        Ok(())
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let file = path_to_bytes(&self.file);
+        let current = self.versions[self.current].id.to_string();
+        let next = self.next.to_string();
+        let keys: Option<Vec<Vec<_>>> = self.deduplication.then(|| {
+            self.versions
+                .iter()
+                .map(|version| {
+                    version
+                        .loaded()
+                        .insertions
+                        .iter()
+                        .map(|insertion| blob_key(&insertion.data))
+                        .collect()
+                })
+                .collect()
+        });
+        let versions = self
+            .versions
+            .iter()
+            .enumerate()
+            .map(|(index, version)| {
+                version.to_nelf_private(
+                    self.compression,
+                    keys.as_ref().map(|keys| &keys[index][..]),
+                )
+            })
+            .to_newline_nelf();
+        let mut header =
+            vec![&file[..], current.as_bytes(), next.as_bytes(), &versions];
+
+        // Version tree files without compression and deduplication keep the
+        // old header, so that older versions of the library can still read
+        // them
+        if self.compression || self.deduplication {
+            header.push(if self.compression { b"1" } else { b"0" });
+        }
+
+        let blobs;
+
+        if let Some(keys) = &keys {
+            let mut stored = HashSet::new();
+            blobs = self
+                .versions
+                .iter()
+                .zip(keys)
+                .flat_map(|(version, keys)| {
+                    version.loaded().insertions.iter().zip(keys)
+                })
+                .filter(|(_, key)| stored.insert(*key))
+                .map(|(insertion, key)| {
+                    let data = if self.compression {
+                        Cow::Owned(deflate(&insertion.data))
+                    } else {
+                        Cow::Borrowed(&insertion.data[..])
+                    };
+
+                    [key.as_bytes(), &data].to_newline_nelf()
+                })
+                .to_newline_nelf();
+            header.push(&blobs);
+        }
+
+        header.to_newline_nelf()
+    }
+
+    fn write(&self) -> SbvcResult<()> {
+        // Writing to a temporary file and renaming it over the version tree
+        // file never leaves the latter half-written
+        let temporary = with_suffix(&self.path, TEMPORARY_EXTENSION);
+        let source = self.serialize();
+        let result = (|| -> SbvcResult<()> {
+            let mut file = fs::File::create(&temporary)?;
+            file.write_all(&source)?;
+
+            if self.durable {
+                sync(&file)?;
+            }
+
+            fs::rename(&temporary, &self.path)?;
+            Ok(())
+        })();
+
+        if let Err(error) = result {
+            let _ = fs::remove_file(&temporary);
+            return Err(error);
+        }
+
+        #[cfg(unix)]
+        if self.durable {
+            sync(&fs::File::open(parent_dir(&self.path))?)?;
+        }
+
+        // A stale index is ignored when opening, so failing to update it is
+        // not an error
+        if self.indexed {
+            let path = with_suffix(&self.path, INDEX_EXTENSION);
+            let index = fs::metadata(&self.path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| index(&source, &self.versions, modified));
+
+            let _ = match index {
+                Some(index) => fs::write(path, index),
+                None => fs::remove_file(path),
+            };
+        }
+
+        Ok(())
+    }
+
+    fn check_writable(&self) -> SbvcResult<()> {
+        check_self_tracking(&self.path, &self.file)?;
+
+        for path in [parent_dir(&self.path), &self.path] {
+            match fs::metadata(path) {
+                Ok(metadata) if metadata.permissions().readonly() => {
+                    return Err(SbvcError::Io(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!("{} is read-only", path.display()),
+                    )));
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn data(&self, version: &Version) -> Vec<u8> {
+        #[cfg(test)]
+        DATA_CALLS.with(|calls| calls.set(calls.get() + 1));
+        let id = version.id;
+        let mut chain = Vec::new();
+        let mut version = version;
+
+        // Walk up to the nearest version whose contents are cached or do not
+        // depend on the base, then patch back down
+        let mut content = loop {
+            if let Some(content) = self.cache.get(version.id) {
+                break content;
+            }
+
+            chain.push(version);
+
+            if version.id == version.base || version.snapshot {
+                break Vec::new();
+            }
+
+            version = &self.versions[self.version(version.base).unwrap()];
+        };
+
+        for version in chain.into_iter().rev() {
+            version.apply(&mut content);
+        }
+
+        self.cache.insert(id, &content);
+        content
+    }
+
+    fn data_by_id(&self, id: u32) -> SbvcResult<Vec<u8>> {
+        let index = self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+        Ok(self.data(&self.versions[index]))
+    }
+
+    // Calls `f` with contents of versions with IDs `first` and `second`,
+    // borrowing them from the cache without copying if both are cached. The
+    // cache stays locked while `f` runs
+    fn with_data_pair<T>(
+        &self,
+        first: u32,
+        second: u32,
+        f: impl FnOnce(&[u8], &[u8]) -> T,
+    ) -> SbvcResult<T> {
+        for id in [first, second] {
+            self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+        }
+
+        {
+            let entries = self.cache.entries();
+            let cached = |id| {
+                entries
+                    .iter()
+                    .find(|&&(cached, _)| cached == id)
+                    .map(|(_, content)| content.as_slice())
+            };
+
+            if let (Some(first), Some(second)) = (cached(first), cached(second))
+            {
+                return Ok(f(first, second));
+            }
+        }
+
+        Ok(f(&self.data_by_id(first)?, &self.data_by_id(second)?))
+    }
+
+    fn rollback(&self) -> SbvcResult<()> {
+        check_self_tracking(&self.path, &self.file)?;
+        fs::write(
+            &self.file,
+            self.checked_data(&self.versions[self.current])?,
+        )?;
+        Ok(())
+    }
+
+    // Same as `data`, but checks contents of base versions against hashes
+    // recorded in their children
+    fn checked_data(&self, version: &Version) -> SbvcResult<Vec<u8>> {
+        let mut chain = Vec::new();
+        let mut version = version;
+
+        while version.id != version.base && !version.snapshot {
+            chain.push(version);
+            version = &self.versions[self.version(version.base).unwrap()];
+        }
+
+        let mut content = self.data(version);
+
+        for version in chain.into_iter().rev() {
+            if version
+                .parent_hash
+                .as_ref()
+                .is_some_and(|parent_hash| *parent_hash != hash(&content))
+            {
+                return Err(SbvcError::InvalidFormat(format!(
+                    "Contents of the base of version with ID {} do not match \
+                    the recorded hash",
+                    version.id
+                )));
+            }
+
+            version.apply(&mut content);
+        }
+
+        Ok(content)
+    }
+
+    /// Returns `true` if the traced file contents are not the same as the
+    /// content for the current version.
+    pub fn is_changed(&self) -> SbvcResult<bool> {
+        Ok(fs::read(&self.file)? != self.data(&self.versions[self.current]))
+    }
+
+    /// Returns the hash of the tracked file contents.
+    ///
+    /// The hash can be compared to the one returned by [`content_hash`] to
+    /// check whether the file contents match some version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs.
+    ///
+    /// [`content_hash`]: Sbvc::content_hash
+    pub fn working_hash(&self) -> SbvcResult<String> {
+        Ok(hash(&fs::read(&self.file)?))
+    }
+
+    /// Returns IDs of versions whose differences are applied, in this order,
+    /// to reconstruct contents of the version with the specified ID, starting
+    /// from the nearest snapshot or initial version.
+    ///
+    /// Recently reconstructed contents are cached, so the actual work can be
+    /// shorter than this plan.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn reconstruction_plan(&self, id: u32) -> SbvcResult<Vec<u32>> {
+        let mut version = &self.versions
+            [self.version(id).ok_or(SbvcError::VersionNotFound(id))?];
+        let mut plan = vec![version.id];
+
+        while version.id != version.base && !version.snapshot {
+            version = &self.versions[self.version(version.base).unwrap()];
+            plan.push(version.id);
+        }
+
+        plan.reverse();
+        Ok(plan)
+    }
+
+    /// Returns the hash of the contents of the version with the specified ID.
+    ///
+    /// The hash is a 64-bit FNV-1a hash in hexadecimal. It is not suitable for
+    /// cryptographic purposes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn content_hash(&self, id: u32) -> SbvcResult<String> {
+        Ok(hash(&self.data_by_id(id)?))
+    }
+
+    /// Returns the contents of the version with the specified ID without
+    /// changing the tracked file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn content(&self, id: u32) -> SbvcResult<Vec<u8>> {
+        self.data_by_id(id)
+    }
+
+    /// Reconstructs the contents of the version with the specified ID and
+    /// checks them against the checksum recorded when the version was
+    /// committed, returning `false` if they differ, e.g. because the version
+    /// tree file was edited by hand.
+    ///
+    /// Versions without a checksum, see [`Version::has_checksum`], cannot be
+    /// verified, so `true` is returned for them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn verify(&self, id: u32) -> SbvcResult<bool> {
+        let index = self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+        let version = &self.versions[index];
+
+        Ok(match &version.checksum {
+            Some(checksum) => *checksum == hash(&self.data(version)),
+            None => true,
+        })
+    }
+
+    /// Returns the specified byte range of the contents of the version with
+    /// the specified ID.
+    ///
+    /// The range is clamped to the length of the contents, so a range past the
+    /// end results in an empty vector.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn content_slice(
+        &self,
+        id: u32,
+        range: Range<usize>,
+    ) -> SbvcResult<Vec<u8>> {
+        let mut content = self.data_by_id(id)?;
+        let end = range.end.min(content.len());
+        let start = range.start.min(end);
+
+        content.truncate(end);
+        content.drain(..start);
+        Ok(content)
+    }
+
+    /// Returns at most the first `max_bytes` bytes of the contents of the
+    /// version with the specified ID, e.g. to show a preview of it in a list
+    /// of versions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn content_preview(
+        &self,
+        id: u32,
+        max_bytes: usize,
+    ) -> SbvcResult<Vec<u8>> {
+        self.content_slice(id, 0..max_bytes)
+    }
+
+    /// Switches to the specified version using its ID.
+    ///
+    /// `rollback` specifies whether the contents of the file should be changed
+    /// according to the version you are switching to. If `true`, file contents
+    /// will be changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error happens or the supplied `id` is not
+    /// found in the version tree. If `rollback` is `false` never fails.
+    ///
+    /// Contents of every base version of the checked out version are checked
+    /// against the hash recorded by its child when rolling back, so corruption
+    /// of the version tree file results in an error.
+    pub fn checkout(&mut self, id: u32, rollback: bool) -> SbvcResult<()> {
+        self.current =
+            self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+        self.working = None;
+
+        if rollback {
+            self.rollback()?;
+        }
+
+        Ok(())
+    }
+
+    /// Same as [`checkout`], but the version is looked up by name as by
+    /// [`version_by_name`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SbvcError::NameNotFound`] if no version has the specified
+    /// name, otherwise same as [`checkout`].
+    ///
+    /// [`checkout`]: Sbvc::checkout
+    /// [`version_by_name`]: Sbvc::version_by_name
+    /// [`SbvcError::NameNotFound`]: SbvcError::NameNotFound
+    pub fn checkout_by_name(
+        &mut self,
+        name: &str,
+        rollback: bool,
+    ) -> SbvcResult<()> {
+        let id = self
+            .version_by_name(name)
+            .ok_or_else(|| SbvcError::NameNotFound(name.to_string()))?
+            .id;
+        self.checkout(id, rollback)
+    }
+
+    /// Switches to the specified version using its ID and saves the current
+    /// version to the version tree file.
+    ///
+    /// Unlike [`checkout`], this method never changes the tracked file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error happens or the supplied `id` is not
+    /// found in the version tree.
+    ///
+    /// [`checkout`]: Sbvc::checkout
+    pub fn select(&mut self, id: u32) -> SbvcResult<()> {
+        self.checkout(id, false)?;
+        self.write()
+    }
+
+    /// Switches to the specified version using its ID, rolling back the file
+    /// contents according to the default rollback policy.
+    ///
+    /// The policy is `false` unless changed with [`set_default_rollback`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`checkout`].
+    ///
+    /// [`set_default_rollback`]: Sbvc::set_default_rollback
+    /// [`checkout`]: Sbvc::checkout
+    pub fn checkout_default(&mut self, id: u32) -> SbvcResult<()> {
+        self.checkout(id, self.default_rollback)
+    }
+
+    /// Same as [`checkout`] with rollback, but first copies the tracked file
+    /// to `backup`, or to its path with `.bak` appended if `backup` is `None`.
+    ///
+    /// The backup is only made if the tracked file exists and its contents
+    /// differ from contents of the version being checked out. Returns `true`
+    /// if the backup was made.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`checkout`]. If the backup cannot be made, the version is not
+    /// checked out.
+    ///
+    /// [`checkout`]: Sbvc::checkout
+    pub fn checkout_with_backup(
+        &mut self,
+        id: u32,
+        backup: Option<&Path>,
+    ) -> SbvcResult<bool> {
+        let index = self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+        check_self_tracking(&self.path, &self.file)?;
+
+        let backed_up = match fs::read(&self.file) {
+            Ok(content) if content != self.data(&self.versions[index]) => {
+                let default = with_suffix(&self.file, BACKUP_EXTENSION);
+                fs::write(backup.unwrap_or(&default), content)?;
+                true
+            }
+            Ok(_) => false,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => false,
+            Err(error) => return Err(error.into()),
+        };
+
+        self.checkout(id, true)?;
+        Ok(backed_up)
+    }
+
+    /// Returns the rollback policy used by [`checkout_default`].
+    ///
+    /// [`checkout_default`]: Sbvc::checkout_default
+    pub fn default_rollback(&self) -> bool {
+        self.default_rollback
+    }
+
+    /// Sets the rollback policy used by [`checkout_default`].
+    ///
+    /// The policy is not stored in the version tree file.
+    ///
+    /// [`checkout_default`]: Sbvc::checkout_default
+    pub fn set_default_rollback(&mut self, rollback: bool) {
+        self.default_rollback = rollback;
+    }
+
+    /// Returns `true` if the version tree file is synced to disk every time it
+    /// is saved.
+    pub fn durable(&self) -> bool {
+        self.durable
+    }
+
+    /// Sets whether the version tree file, and on Unix the directory
+    /// containing it, is synced to disk every time it is saved, so that saved
+    /// changes, e.g. new versions, survive a crash or a power loss. Off by
+    /// default, since syncing is slow.
+    ///
+    /// The setting is not stored in the version tree file.
+    pub fn set_durable(&mut self, durable: bool) {
+        self.durable = durable;
+    }
+
+    /// Returns `true` if insertions are compressed in the version tree file.
+    #[cfg(feature = "compression")]
+    pub fn compression(&self) -> bool {
+        self.compression
+    }
+
+    /// Sets whether contents of insertions are compressed with DEFLATE in the
+    /// version tree file, which makes it a lot smaller for text files. Off by
+    /// default. Compressed version tree files are never opened lazily.
+    ///
+    /// The setting is stored in the version tree file the next time it is
+    /// saved. Version tree files are opened with the setting they were saved
+    /// with.
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.compression = enabled;
+    }
+
+    /// Returns `true` if identical insertions are stored only once in the
+    /// version tree file.
+    pub fn deduplication(&self) -> bool {
+        self.deduplication
+    }
+
+    /// Sets whether contents of insertions are stored in the version tree file
+    /// once per distinct contents, with versions referring to them by their
+    /// SHA-256 hash, which saves space when the same bytes are inserted by
+    /// many versions, e.g. on different branches. Off by default.
+    ///
+    /// The setting is stored in the version tree file the next time it is
+    /// saved. Version tree files are opened with the setting they were saved
+    /// with.
+    pub fn set_deduplication(&mut self, enabled: bool) {
+        self.deduplication = enabled;
+    }
+
+    /// Returns `true` if an index is kept next to the version tree file.
+    pub fn indexed(&self) -> bool {
+        self.indexed
+    }
+
+    /// Sets whether an index recording metadata of every version and where its
+    /// inserted bytes are located in the version tree file is kept next to it,
+    /// in a file with `.idx` appended to its path, so that opening it reads
+    /// only the header of the file and the inserted bytes. With [`open_lazy`],
+    /// inserted bytes are read from the file when they are first needed.
+    /// Enabling it saves the index right away, disabling it removes the index.
+    ///
+    /// Version tree files are opened with the index enabled if it exists. An
+    /// index whose length or modification time does not match the version
+    /// tree file, e.g. because it was changed by an older version of the
+    /// library, is ignored and replaced the next time the version tree file is
+    /// saved.
+    ///
+    /// [`open_lazy`]: Sbvc::open_lazy
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs.
+    pub fn set_indexed(&mut self, indexed: bool) -> SbvcResult<()> {
+        self.indexed = indexed;
+
+        if indexed {
+            self.write()
+        } else {
+            match fs::remove_file(with_suffix(&self.path, INDEX_EXTENSION)) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                    Err(error.into())
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// Sets the hook called with the contents of every new version before it
+    /// is committed, replacing the previous one.
+    ///
+    /// If the hook returns an error, the commit fails with
+    /// [`SbvcError::HookRejected`] and the version tree is left unchanged.
+    ///
+    /// [`SbvcError::HookRejected`]: SbvcError::HookRejected
+    pub fn set_pre_commit_hook(
+        &mut self,
+        hook: impl Fn(&[u8]) -> Result<(), String> + Send + Sync + 'static,
+    ) {
+        self.pre_commit_hook = Hook(Some(Arc::new(hook)));
+    }
+
+    /// Removes the hook set by [`set_pre_commit_hook`].
+    ///
+    /// [`set_pre_commit_hook`]: Sbvc::set_pre_commit_hook
+    pub fn clear_pre_commit_hook(&mut self) {
+        self.pre_commit_hook = Hook::default();
+    }
+
+    /// Returns the coalesce window set by [`set_coalesce_window`].
+    ///
+    /// [`set_coalesce_window`]: Sbvc::set_coalesce_window
+    pub fn coalesce_window(&self) -> Option<Duration> {
+        self.coalesce_window
+    }
+
+    /// Sets the coalesce window for commits.
+    ///
+    /// If a window is set, committing while the current version is younger
+    /// than the window and has no children amends the current version instead
+    /// of creating a new one. If `update_date` is `true`, the creation date of
+    /// the amended version is updated to the time of the commit. The initial
+    /// version is never amended.
+    ///
+    /// The window is not stored in the version tree file. It is not set by
+    /// default.
+    pub fn set_coalesce_window(
+        &mut self,
+        window: Option<Duration>,
+        update_date: bool,
+    ) {
+        self.coalesce_window = window;
+        self.coalesce_updates_date = update_date;
+    }
+
+    /// Saves changes in the file to a new version branching from the current
+    /// one.
+    ///
+    /// This method automatically checks out the newly created version.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if an IO error occurs or if the version tree file is
+    /// clearly unwritable, in which case no version is created.
+    pub fn commit(&mut self) -> SbvcResult<()> {
+        self.commit_private(None, None)
+    }
+
+    /// Same as [`commit`], but the new version is given the specified name
+    /// instead of the default one, without saving the version tree file
+    /// twice as [`commit`] followed by [`rename`] would.
+    ///
+    /// If the commit is coalesced into the current version, the current
+    /// version is renamed.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`commit`].
+    ///
+    /// [`commit`]: Sbvc::commit
+    /// [`rename`]: Sbvc::rename
+    pub fn commit_with_name(&mut self, name: &str) -> SbvcResult<()> {
+        self.commit_private(Some(name), None)
+    }
+
+    /// Same as [`commit`], but the new version is given the specified
+    /// description, which can be changed later with [`set_description`].
+    ///
+    /// If the commit is coalesced into the current version, the description of
+    /// the current version is replaced.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`commit`].
+    ///
+    /// [`commit`]: Sbvc::commit
+    /// [`set_description`]: Sbvc::set_description
+    pub fn commit_with_message(&mut self, message: &str) -> SbvcResult<()> {
+        self.commit_private(None, Some(message))
+    }
+
+    fn commit_private(
+        &mut self,
+        name: Option<&str>,
+        description: Option<&str>,
+    ) -> SbvcResult<()> {
+        self.check_writable()?;
+        let content = fs::read(&self.file)?;
+        self.commit_content(
+            content,
+            Some(self.file.clone()),
+            name,
+            description,
+        )?;
+        Ok(())
+    }
+
+    /// Saves contents read from `reader` to a new version branching from the
+    /// current one, and returns the ID of the new version.
+    ///
+    /// The reader is read until EOF. This method automatically checks out the
+    /// newly created version, but does not change the tracked file.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`commit`].
+    ///
+    /// [`commit`]: Sbvc::commit
+    pub fn commit_from(&mut self, mut reader: impl Read) -> SbvcResult<u32> {
+        self.check_writable()?;
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        self.commit_content(content, None, None, None)
+    }
+
+    /// Same as [`commit`], but the new version branches from the version with
+    /// the specified ID instead of the current one, and its ID is returned.
+    ///
+    /// The new version is checked out, since its contents are the contents of
+    /// the tracked file.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`commit`]. Also returns an error if the base version is not
+    /// found in the version tree.
+    ///
+    /// [`commit`]: Sbvc::commit
+    pub fn commit_onto(&mut self, base: u32) -> SbvcResult<u32> {
+        let index =
+            self.version(base).ok_or(SbvcError::VersionNotFound(base))?;
+        self.check_writable()?;
+        let content = fs::read(&self.file)?;
+        self.pre_commit_hook.check(&content)?;
+        self.push_version(
+            index,
+            content,
+            Some(self.file.clone()),
+            DEFAULT_VERSION_NAME,
+            "",
+            SystemTime::now(),
+        )
+    }
+
+    /// Same as [`commit`], but the new version is created with the specified
+    /// date instead of the current time, e.g. to preserve dates of versions
+    /// imported from elsewhere.
+    ///
+    /// Dates are stored with the precision of a second. The commit is never
+    /// coalesced into the current version.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`commit`]. Also returns an error if `when` is before the Unix
+    /// epoch, since such dates cannot be stored.
+    ///
+    /// [`commit`]: Sbvc::commit
+    pub fn commit_at(&mut self, when: SystemTime) -> SbvcResult<u32> {
+        if when < SystemTime::UNIX_EPOCH {
+            return Err(SbvcError::InvalidOperation(
+                "Dates before the Unix epoch cannot be stored".to_string(),
+            ));
+        }
+
+        self.check_writable()?;
+        let content = fs::read(&self.file)?;
+        self.pre_commit_hook.check(&content)?;
+        self.push_version(
+            self.current,
+            content,
+            Some(self.file.clone()),
+            DEFAULT_VERSION_NAME,
+            "",
+            when,
+        )
+    }
+
+    /// Returns the in-memory working buffer, which starts out with contents of
+    /// the current version and can be saved with [`commit_working`] without
+    /// touching the tracked file.
+    ///
+    /// The buffer is discarded when another version is checked out or the
+    /// current version is changed by other methods.
+    ///
+    /// [`commit_working`]: Sbvc::commit_working
+    pub fn edit(&mut self) -> &mut Vec<u8> {
+        if self.working.is_none() {
+            self.working = Some(self.data(&self.versions[self.current]));
+        }
+
+        self.working.as_mut().unwrap()
+    }
+
+    /// Saves the working buffer returned by [`edit`] to a new version
+    /// branching from the current one, and returns the ID of the new version.
+    ///
+    /// The new version is checked out and the buffer is kept, so that editing
+    /// can continue. The tracked file is not changed.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`commit`].
+    ///
+    /// [`edit`]: Sbvc::edit
+    /// [`commit`]: Sbvc::commit
+    pub fn commit_working(&mut self) -> SbvcResult<u32> {
+        self.check_writable()?;
+        let content = self.edit().clone();
+        let id = self.commit_content(content.clone(), None, None, None)?;
+        self.working = Some(content);
+        Ok(id)
+    }
+
+    /// Saves only the changes in the file that intersect any of the specified
+    /// byte ranges of the file to a new version branching from the current
+    /// one, and returns the ID of the new version.
+    ///
+    /// Changes are groups of adjacent changed bytes and are either saved as a
+    /// whole or not saved at all. Changes consisting only of deletions are
+    /// saved if the place where the bytes were deleted is within any of the
+    /// ranges or at their end. Unsaved changes stay in the file, so they can be
+    /// committed later. This method automatically checks out the newly created
+    /// version.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`commit`].
+    ///
+    /// [`commit`]: Sbvc::commit
+    pub fn commit_ranges(
+        &mut self,
+        ranges: &[Range<usize>],
+    ) -> SbvcResult<u32> {
+        self.check_writable()?;
+        let new = fs::read(&self.file)?;
+        let old = self.data(&self.versions[self.current]);
+        let operations = operations(&old, &new);
+
+        let mut content = Vec::new();
+        let (mut old_index, mut new_index, mut index) = (0, 0, 0);
+
+        while index < operations.len() {
+            if let Operation::Equal = operations[index] {
+                content.push(old[old_index]);
+                old_index += 1;
+                new_index += 1;
+                index += 1;
+                continue;
+            }
+
+            let (old_start, new_start) = (old_index, new_index);
+
+            while index < operations.len() {
+                match operations[index] {
+                    Operation::Equal => break,
+                    Operation::Deletion => old_index += 1,
+                    Operation::Insertion => new_index += 1,
+                }
+
+                index += 1;
+            }
+
+            let included = ranges.iter().any(|range| {
+                if new_start == new_index {
+                    range.start <= new_start && new_start <= range.end
+                } else {
+                    range.start < new_index && new_start < range.end
+                }
+            });
+
+            if included {
+                content.extend_from_slice(&new[new_start..new_index]);
+            } else {
+                content.extend_from_slice(&old[old_start..old_index]);
+            }
+        }
+
+        self.commit_content(content, Some(self.file.clone()), None, None)
+    }
+
+    fn commit_content(
+        &mut self,
+        content: Vec<u8>,
+        source_path: Option<PathBuf>,
+        name: Option<&str>,
+        description: Option<&str>,
+    ) -> SbvcResult<u32> {
+        self.pre_commit_hook.check(&content)?;
+        let now = SystemTime::now();
+        self.working = None;
+        let current = &self.versions[self.current];
+
+        if let Some(window) = self.coalesce_window {
+            if current.id != current.base
+                && now.duration_since(current.date).unwrap_or(Duration::ZERO)
+                    < window
+                && current.moved_from.is_none()
+                && self.children_of(current.id).next().is_none()
+            {
+                let base = self
+                    .data(&self.versions[self.version(current.base).unwrap()]);
+                let signature = self.sign(&base, &content);
+                let current = &mut self.versions[self.current];
+
+                current.set_difference(&base, &content);
+                current.source_path = source_path;
+                self.cache.insert(current.id, &content);
+                current.signature = signature;
+                self.deleted = None;
+
+                if let Some(name) = name {
+                    current.name = name.to_string();
+                }
+
+                if let Some(description) = description {
+                    current.description = description.to_string();
+                }
+
+                if self.coalesce_updates_date {
+                    current.date = now;
+                }
+
+                let id = current.id;
+                self.write()?;
+                return Ok(id);
+            }
+        }
+
+        self.push_version(
+            self.current,
+            content,
+            source_path,
+            name.unwrap_or(DEFAULT_VERSION_NAME),
+            description.unwrap_or_default(),
+            now,
+        )
+    }
+
+    fn push_version(
+        &mut self,
+        base: usize,
+        content: Vec<u8>,
+        source_path: Option<PathBuf>,
+        name: &str,
+        description: &str,
+        date: SystemTime,
+    ) -> SbvcResult<u32> {
+        let id = self.add_version(
+            base,
+            content,
+            source_path,
+            name,
+            description,
+            date,
+        );
+        self.write()?;
+        Ok(id)
+    }
+
+    // Same as `push_version`, but does not save the version tree file
+    fn add_version(
+        &mut self,
+        base: usize,
+        content: Vec<u8>,
+        source_path: Option<PathBuf>,
+        name: &str,
+        description: &str,
+        date: SystemTime,
+    ) -> u32 {
+        self.working = None;
+        self.deleted = None;
+        let id = self.next;
+        let base_id = self.versions[base].id;
+        // TODO Optimize for big files
+        let base = self.data(&self.versions[base]);
+        let (difference, snapshot) = difference(&content, &base);
+        let signature = self.sign(&base, &content);
+
+        self.versions.push(Version {
+            id,
+            base: base_id,
+            name: name.to_string(),
+            description: description.to_string(),
+            date,
+            difference,
+            extra_parents: Vec::new(),
+            source_path,
+            moved_from: None,
+            snapshot,
+            signature,
+            parent_hash: Some(hash(&base)),
+            checksum: Some(hash(&content)),
+            lazy: None,
+        });
+        self.next += 1;
+        self.current = self.versions.len() - 1;
+        self.cache.insert(id, &content);
+        id
+    }
+
+    /// Creates a new version branching from the current one, whose contents
+    /// are contents of the current version with changes made by the version
+    /// with the specified ID undone, and checks it out. The new version is
+    /// named `revert <id>`.
+    ///
+    /// Changes made after the reverted version are kept. Like
+    /// [`commit_from`], this method does not change the tracked file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs, if the version is not found in
+    /// the version tree or if it is the initial version, which has no changes
+    /// to undo.
+    ///
+    /// [`commit_from`]: Sbvc::commit_from
+    pub fn revert(&mut self, id: u32) -> SbvcResult<()> {
+        let target = &self.versions
+            [self.version(id).ok_or(SbvcError::VersionNotFound(id))?];
+
+        if target.id == target.base {
+            return Err(SbvcError::InvalidOperation(
+                "The initial version cannot be reverted".to_string(),
+            ));
+        }
+
+        self.check_writable()?;
+        let base =
+            self.data(&self.versions[self.version(target.base).unwrap()]);
+        let content = reverted(
+            &base,
+            &self.data(target),
+            &self.data(&self.versions[self.current]),
+        );
+        self.pre_commit_hook.check(&content)?;
+        self.push_version(
+            self.current,
+            content,
+            None,
+            &format!("revert {}", id),
+            "",
+            SystemTime::now(),
+        )?;
+        Ok(())
+    }
+
+    /// Starts merging the version with the specified ID into the current
+    /// version.
+    ///
+    /// Changes both versions made since their nearest common ancestor are
+    /// combined byte by byte. Changes that overlap or touch and are not the
+    /// same are reported as conflicts, which have to be resolved through the
+    /// returned [`MergeSession`] before the result is committed with
+    /// [`MergeSession::finalize`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn merge(&mut self, id: u32) -> SbvcResult<MergeSession<'_>> {
+        let theirs = self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+        let base = self.nearest_common_ancestor(
+            self.versions[self.current].id,
+            id,
+            true,
+        )?;
+
+        let (clean, conflicts) = merged(
+            &self.data_by_id(base)?,
+            &self.data(&self.versions[self.current]),
+            &self.data(&self.versions[theirs]),
+        );
+
+        Ok(MergeSession { sbvc: self, id, clean, conflicts, finalized: false })
+    }
+
+    /// Same as [`merge`], but commits the result right away, failing if there
+    /// are any conflicts.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs, if the version is not found in
+    /// the version tree, or [`SbvcError::MergeConflict`] with the first
+    /// conflict, in which case nothing is committed.
+    ///
+    /// [`merge`]: Sbvc::merge
+    /// [`SbvcError::MergeConflict`]: SbvcError::MergeConflict
+    pub fn merge_clean(&mut self, id: u32) -> SbvcResult<()> {
+        self.merge(id)?.finalize()?;
+        Ok(())
+    }
+
+    /// Renames the current version.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when an IO error occurs.
+    pub fn rename(&mut self, name: &str) -> SbvcResult<()> {
+        self.versions[self.current].name.clear();
+        self.versions[self.current].name.push_str(name);
+        self.write()
+    }
+
+    /// Sets the description of the current version.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when an IO error occurs.
+    pub fn set_description(&mut self, desc: &str) -> SbvcResult<()> {
+        self.versions[self.current].description.clear();
+        self.versions[self.current].description.push_str(desc);
+        self.write()
+    }
+
+    /// Returns the most recently created version with the specified name.
+    ///
+    /// Names are not unique, so versions created at the same second are told
+    /// apart by their IDs.
+    pub fn version_by_name(&self, name: &str) -> Option<&Version> {
+        self.versions
+            .iter()
+            .filter(|version| version.name == name)
+            .max_by_key(|version| (version.date, version.id))
+    }
+
+    /// Returns `true` if no version other than the one with ID `except` has
+    /// the specified name.
+    pub fn name_is_unique(&self, name: &str, except: Option<u32>) -> bool {
+        !self
+            .versions
+            .iter()
+            .any(|version| Some(version.id) != except && version.name == name)
+    }
+
+    /// Deletes version with the selected ID.
+    ///
+    /// This method does not delete the initial version.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when an IO error occurs.
+    pub fn delete(&mut self) -> SbvcResult<()> {
+        let current = self.current;
+        self.checkout(self.versions[self.current].base, true)?;
+        self.deleted = Some(self.delete_private(current));
+        self.write()
+    }
+
+    /// Deletes the version with the specified ID together with versions
+    /// branching from it.
+    ///
+    /// If the current version would be deleted, this method refuses to delete
+    /// anything unless `force` is `true`, in which case the base of the
+    /// deleted version is checked out with rollback first, like [`delete`]
+    /// does. This method does not delete the initial version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs, if the version is not found in
+    /// the version tree, or if the current version would be deleted and
+    /// `force` is `false`.
+    ///
+    /// [`delete`]: Sbvc::delete
+    pub fn delete_version(&mut self, id: u32, force: bool) -> SbvcResult<()> {
+        let index = self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+        let current = self.versions[self.current].id;
+
+        if id == self.versions[index].base {
+            return Ok(());
+        }
+
+        if current == id || self.is_ancestor(id, current, false)? {
+            if !force {
+                return Err(SbvcError::WouldOrphanCurrent(id));
+            }
+
+            self.checkout(self.versions[index].base, true)?;
+        }
+
+        self.deleted = Some(self.delete_private(index));
+        self.write()
+    }
+
+    /// Restores the versions removed by the last call to [`delete`] or
+    /// [`delete_version`], keeping their IDs, and saves the version tree file.
+    ///
+    /// Only the last deletion made by this instance can be undone, and only
+    /// once. Any other change to the version tree discards it. The current
+    /// version and the tracked file are left unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs, if there is no deletion to
+    /// undo, or if the base of the deleted versions no longer exists.
+    ///
+    /// [`delete`]: Sbvc::delete
+    /// [`delete_version`]: Sbvc::delete_version
+    pub fn undo_delete(&mut self) -> SbvcResult<()> {
+        let deleted = self.deleted.take().ok_or_else(|| {
+            SbvcError::InvalidOperation("Nothing to undo".to_string())
+        })?;
+        let restored: HashSet<_> =
+            deleted.versions.iter().map(|version| version.id).collect();
+
+        if let Some(version) = deleted.versions.iter().find(|version| {
+            !restored.contains(&version.base)
+                && self.version(version.base).is_none()
+        }) {
+            return Err(SbvcError::VersionNotFound(version.base));
+        }
+
+        self.versions.extend(deleted.versions);
+
+        for (id, parent) in deleted.extra_parents {
+            if let Some(index) = self.version(id) {
+                let extra_parents = &mut self.versions[index].extra_parents;
+
+                if !extra_parents.contains(&parent) {
+                    extra_parents.push(parent);
+                }
+            }
+        }
+
+        self.write()
+    }
+
+    fn delete_private(&mut self, index: usize) -> Deleted {
+        let id = self.versions[index].id;
+
+        if id == self.versions[index].base {
+            return Deleted::default();
+        }
+
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+
+        for version in &self.versions {
+            if version.id != version.base {
+                children.entry(version.base).or_default().push(version.id);
+            }
+        }
+
+        let mut deleted = HashSet::new();
+        let mut stack = vec![id];
+
+        while let Some(id) = stack.pop() {
+            stack.extend(children.remove(&id).unwrap_or_default());
+            deleted.insert(id);
+        }
+
+        self.remove_all(&deleted)
+    }
+
+    // Removes versions with the specified IDs, which must not include the
+    // current one, and returns what was removed
+    fn remove_all(&mut self, ids: &HashSet<u32>) -> Deleted {
+        let current = self.versions[self.current].id;
+        let (versions, remaining) = mem::take(&mut self.versions)
+            .into_iter()
+            .partition(|version| ids.contains(&version.id));
+        let mut extra_parents = Vec::new();
+        self.versions = remaining;
+
+        for version in &mut self.versions {
+            version.extra_parents.retain(|&parent| {
+                if ids.contains(&parent) {
+                    extra_parents.push((version.id, parent));
+                }
+
+                !ids.contains(&parent)
+            });
+        }
+
+        self.current = self.version(current).unwrap();
+        Deleted { versions, extra_parents }
+    }
+
+    /// Replaces versions on the path from the version with ID `from` to the
+    /// version with ID `to` with a single version, which keeps the ID,
+    /// metadata and contents of the latter, but branches from the former.
+    ///
+    /// Versions between them are deleted, so none of them may have other
+    /// children. Children of the version with ID `to` stay its children.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs, if either of the versions is not
+    /// found in the version tree, if `from` is not an ancestor of `to`, if a
+    /// version between them has other children or if one of them is the
+    /// current version.
+    pub fn squash(&mut self, from: u32, to: u32) -> SbvcResult<()> {
+        let index = self.version(to).ok_or(SbvcError::VersionNotFound(to))?;
+
+        if from == to {
+            self.version(from).ok_or(SbvcError::VersionNotFound(from))?;
+            return Ok(());
+        }
+
+        if !self.is_ancestor(from, to, false)? {
+            return Err(SbvcError::NotAncestor(from, to));
+        }
+
+        let mut squashed = HashSet::new();
+        let mut id = self.versions[index].base;
+
+        while id != from {
+            if self.children_of(id).count() > 1 {
+                return Err(SbvcError::InvalidOperation(format!(
+                    "Version with ID {} between versions with IDs {} and {} \
+                    has other children",
+                    id, from, to
+                )));
+            }
+
+            if id == self.versions[self.current].id {
+                return Err(SbvcError::WouldOrphanCurrent(id));
+            }
+
+            squashed.insert(id);
+            id = self.versions[self.version(id).unwrap()].base;
+        }
+
+        let base = self.data(&self.versions[self.version(from).unwrap()]);
+        let content = self.data(&self.versions[index]);
+        let signature = self.sign(&base, &content);
+        let version = &mut self.versions[index];
+
+        version.base = from;
+        version.set_difference(&base, &content);
+        version.signature = signature;
+
+        self.remove_all(&squashed);
+        self.deleted = None;
+        self.write()
+    }
+
+    /// Deletes the current version, but unlike [`delete`], keeps its children
+    /// by making them branch from the base of the deleted version.
+    ///
+    /// Contents of the children stay the same. This method does not delete the
+    /// initial version.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when an IO error occurs.
+    ///
+    /// [`delete`]: Sbvc::delete
+    pub fn delete_keep_children(&mut self) -> SbvcResult<()> {
+        let current = self.current;
+        let Version { id, base, .. } = self.versions[current];
+
+        if id == base {
+            return self.checkout(base, true);
+        }
+
+        let content = self.data(&self.versions[self.version(base).unwrap()]);
+        let differences: Vec<_> = self
+            .versions
+            .iter()
+            .enumerate()
+            .filter(|&(_, version)| version.base == id)
+            .map(|(index, version)| {
+                let data = self.data(version);
+                let signature = self.sign(&content, &data);
+                (index, data, signature)
+            })
+            .collect();
+
+        self.checkout(base, true)?;
+
+        for (index, data, signature) in differences {
+            self.versions[index].base = base;
+            self.versions[index].set_difference(&content, &data);
+            self.versions[index].signature = signature;
+        }
+
+        self.remove(current);
+        self.deleted = None;
+        self.write()
+    }
+
+    /// Makes the current version branch from the version with the specified
+    /// ID, keeping its contents the same.
+    ///
+    /// The moved version stays current, and its children stay its children.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs, if the version is not found in
+    /// the version tree, if the current version is the initial one, or if the
+    /// new base is the current version or its descendant.
+    pub fn move_current_to(&mut self, id: u32) -> SbvcResult<()> {
+        let index = self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+        let current = &self.versions[self.current];
+
+        if current.id == current.base {
+            return Err(SbvcError::InvalidOperation(
+                "Cannot move the initial version".to_string(),
+            ));
+        }
+
+        if id == current.id || self.is_ancestor(current.id, id, false)? {
+            return Err(SbvcError::InvalidOperation(format!(
+                "Version with ID {} cannot branch from its descendant with ID \
+                {}",
+                current.id, id
+            )));
+        }
+
+        let content = self.data(current);
+        let base = self.data(&self.versions[index]);
+        let signature = self.sign(&base, &content);
+        self.versions[self.current].base = id;
+        self.versions[self.current].set_difference(&base, &content);
+        self.versions[self.current].signature = signature;
+
+        self.deleted = None;
+        self.write()
+    }
+
+    fn remove(&mut self, index: usize) {
+        let id = self.versions.remove(index).id;
+
+        if index < self.current {
+            self.current -= 1;
+        }
+
+        for version in &mut self.versions {
+            version.extra_parents.retain(|&parent| parent != id);
+        }
+    }
+
+    /// Returns the path to the version tree file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path to the tracked file.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Sets the tracked file for this version tree.
+    ///
+    /// # Errors
+    ///
+    /// Fails if an IO error occurs or if `file` is the version tree file.
+    pub fn set_file(&mut self, file: PathBuf) -> SbvcResult<()> {
+        check_self_tracking(&self.path, &file)?;
+        self.file = file;
+        self.write()
+    }
+
+    /// Same as [`set_file`], but if `record` is `true`, also records the move
+    /// in history by creating a version with the same contents as the current
+    /// one that stores the old and the new paths of the tracked file.
+    ///
+    /// The recorded paths are available through [`Version::moved_from`] and
+    /// [`Version::source_path`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if an IO error occurs or if `file` is the version tree file. If
+    /// `record` is `true`, also fails if the pre-commit hook rejects the
+    /// contents.
+    ///
+    /// [`set_file`]: Sbvc::set_file
+    pub fn relocate(&mut self, file: PathBuf, record: bool) -> SbvcResult<()> {
+        check_self_tracking(&self.path, &file)?;
+
+        if record {
+            self.check_writable()?;
+            let content = self.data(&self.versions[self.current]);
+            self.pre_commit_hook.check(&content)?;
+            let signature = self.sign(&content, &content);
+            self.versions.push(Version {
+                id: self.next,
+                base: self.versions[self.current].id,
+                name: RELOCATION_VERSION_NAME.to_string(),
+                description: String::new(),
+                date: SystemTime::now(),
+                difference: OwnedDifference::empty(),
+                extra_parents: Vec::new(),
+                source_path: Some(file.clone()),
+                moved_from: Some(self.file.clone()),
+                snapshot: false,
+                signature,
+                parent_hash: Some(hash(&content)),
+                checksum: Some(hash(&content)),
+                lazy: None,
+            });
+            self.next += 1;
+            self.current = self.versions.len() - 1;
+        }
+
+        self.file = file;
+        self.write()
+    }
+
+    /// Returns a reference to the current version (For info).
+    ///
+    /// The current version always exists, since it is checked when the
+    /// version tree is opened and every method that removes versions checks
+    /// out another one first. See [`try_current`] for a version of this method
+    /// that does not rely on that.
+    ///
+    /// [`try_current`]: Sbvc::try_current
+    pub fn current(&self) -> &Version {
+        &self.versions[self.current]
+    }
+
+    /// Same as [`current`], but returns an error instead of panicking if the
+    /// current version does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the current version does not exist, which can
+    /// only be caused by a bug in this library.
+    ///
+    /// [`current`]: Sbvc::current
+    pub fn try_current(&self) -> SbvcResult<&Version> {
+        self.versions.get(self.current).ok_or_else(|| {
+            SbvcError::InvalidOperation(format!(
+                "Current version index {} is out of bounds of {} versions",
+                self.current,
+                self.versions.len()
+            ))
+        })
+    }
+
+    /// Sets the additional parents of the version with the specified ID, e.g.
+    /// the second parent of a merge.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs or if the version or one of the
+    /// parents is not found in the version tree.
+    pub fn set_extra_parents(
+        &mut self,
+        id: u32,
+        parents: Vec<u32>,
+    ) -> SbvcResult<()> {
+        let index = self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+
+        if let Some(&parent) =
+            parents.iter().find(|&&parent| self.version(parent).is_none())
+        {
+            return Err(SbvcError::VersionNotFound(parent));
+        }
+
+        self.versions[index].extra_parents = parents;
+        self.write()
+    }
+
+    /// Returns IDs of all ancestors of the version with the specified ID,
+    /// nearest first.
+    ///
+    /// If `extra_parents` is `true`, additional parents of versions are
+    /// traversed as well as their bases.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn ancestors(
+        &self,
+        id: u32,
+        extra_parents: bool,
+    ) -> SbvcResult<Vec<u32>> {
+        let mut result = Vec::new();
+        let mut queue = vec![id];
+        let mut index = 0;
+
+        self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+
+        while index < queue.len() {
+            let version = match self.version(queue[index]) {
+                Some(version) => &self.versions[version],
+                None => {
+                    index += 1;
+                    continue;
+                }
+            };
+
+            let mut parents = vec![version.base];
+
+            if extra_parents {
+                parents.extend_from_slice(&version.extra_parents);
+            }
+
+            for parent in parents {
+                if parent != version.id && !queue.contains(&parent) {
+                    queue.push(parent);
+                    result.push(parent);
+                }
+            }
+
+            index += 1;
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the ID of the nearest version both versions with the specified
+    /// IDs descend from, which can be one of them if it is an ancestor of the
+    /// other. In the worst case, this is the initial version.
+    ///
+    /// Extra parents are not followed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either of the versions is not found in the version
+    /// tree, or [`SbvcError::InvalidOperation`] if the versions have no common
+    /// ancestor, which is only possible in damaged version trees.
+    ///
+    /// [`SbvcError::InvalidOperation`]: SbvcError::InvalidOperation
+    pub fn common_ancestor(&self, a: u32, b: u32) -> SbvcResult<u32> {
+        self.nearest_common_ancestor(a, b, false)
+    }
+
+    fn nearest_common_ancestor(
+        &self,
+        a: u32,
+        b: u32,
+        extra_parents: bool,
+    ) -> SbvcResult<u32> {
+        let mut ancestors_of_b = self.ancestors(b, extra_parents)?;
+        ancestors_of_b.push(b);
+
+        let mut ancestors_of_a = self.ancestors(a, extra_parents)?;
+        ancestors_of_a.insert(0, a);
+
+        ancestors_of_a
+            .into_iter()
+            .find(|ancestor| ancestors_of_b.contains(ancestor))
+            .ok_or_else(|| {
+                SbvcError::InvalidOperation(format!(
+                    "Versions with IDs {} and {} have no common ancestor",
+                    a, b
+                ))
+            })
+    }
+
+    /// Returns `true` if the version with ID `ancestor` is an ancestor of the
+    /// version with ID `id`.
+    ///
+    /// `extra_parents` has the same meaning as in [`ancestors`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either of the versions is not found in the version
+    /// tree.
+    ///
+    /// [`ancestors`]: Sbvc::ancestors
+    pub fn is_ancestor(
+        &self,
+        ancestor: u32,
+        id: u32,
+        extra_parents: bool,
+    ) -> SbvcResult<bool> {
+        self.version(ancestor).ok_or(SbvcError::VersionNotFound(ancestor))?;
+        Ok(self.ancestors(id, extra_parents)?.contains(&ancestor))
+    }
+
+    /// Returns versions on the path from the initial version to the version
+    /// with ID `tip`, both inclusive, in this order.
+    ///
+    /// Extra parents are not followed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn versions_on_branch(&self, tip: u32) -> SbvcResult<Vec<&Version>> {
+        let mut ids = self.ancestors(tip, false)?;
+        ids.reverse();
+        ids.push(tip);
+
+        Ok(ids
+            .into_iter()
+            .map(|id| &self.versions[self.version(id).unwrap()])
+            .collect())
+    }
+
+    /// Same as [`versions_on_branch`], but ordered from the version with the
+    /// specified ID to the initial version, e.g. to walk its history.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    ///
+    /// [`versions_on_branch`]: Sbvc::versions_on_branch
+    pub fn ancestry(&self, id: u32) -> SbvcResult<Vec<&Version>> {
+        let mut versions = self.versions_on_branch(id)?;
+        versions.reverse();
+        Ok(versions)
+    }
+
+    /// Writes a numbered patch file into `dir` for each version on the path
+    /// from the version with ID `from` (exclusive) to the version with ID `to`
+    /// (inclusive), and returns paths to the written files.
+    ///
+    /// Patch files are named `0001-<name>.patch`, `0002-<name>.patch`, etc.
+    /// and contain the difference of the version from its base, as well as
+    /// its metadata. Such series can be applied with [`apply_patch_series`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs, if either of the versions is
+    /// not found in the version tree, or if `from` is not an ancestor of `to`.
+    ///
+    /// [`apply_patch_series`]: Sbvc::apply_patch_series
+    pub fn export_patch_series(
+        &self,
+        from: u32,
+        to: u32,
+        dir: &Path,
+    ) -> SbvcResult<Vec<PathBuf>> {
+        self.version(from).ok_or(SbvcError::VersionNotFound(from))?;
+        self.version(to).ok_or(SbvcError::VersionNotFound(to))?;
+
+        if from != to && !self.is_ancestor(from, to, false)? {
+            return Err(SbvcError::NotAncestor(from, to));
+        }
+
+        let mut chain = Vec::new();
+
+        if from != to {
+            chain.push(to);
+            chain.extend(
+                self.ancestors(to, false)?
+                    .into_iter()
+                    .take_while(|&id| id != from),
+            );
+        }
+
+        fs::create_dir_all(dir)?;
+
+        let mut paths = Vec::new();
+
+        for (number, id) in chain.into_iter().rev().enumerate() {
+            let version = &self.versions[self.version(id).unwrap()];
+            let name: String = version
+                .name
+                .chars()
+                .map(|ch| if ch.is_alphanumeric() { ch } else { '-' })
+                .collect();
+            let path = dir.join(format!("{:04}-{}.patch", number + 1, name));
+
+            fs::write(&path, version.to_nelf())?;
+            paths.push(path);
+        }
+
+        Ok(paths)
+    }
+
+    /// Reads numbered patch files written by [`export_patch_series`] from
+    /// `dir` and commits each of them in order as a new version on top of the
+    /// current one, restoring their names and creation dates.
+    ///
+    /// The last applied version is checked out, but the tracked file is not
+    /// changed. Signatures stored in patch files are dropped, and applied
+    /// versions are signed if a signing key is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs, if a patch file cannot be
+    /// parsed or applied to its base, or if the pre-commit hook rejects the
+    /// contents it results in. In the latter two cases, the patches preceding
+    /// the failed one stay applied.
+    ///
+    /// [`export_patch_series`]: Sbvc::export_patch_series
+    pub fn apply_patch_series(&mut self, dir: &Path) -> SbvcResult<()> {
+        self.check_writable()?;
+        let mut paths = Vec::new();
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|extension| extension == "patch") {
+                paths.push(path);
+            }
+        }
+
+        paths.sort();
+
+        let mut content = self.data(&self.versions[self.current]);
+        self.working = None;
+
+        for path in paths {
+            let mut patch = match Version::from_nelf(&fs::read(&path)?) {
+                Ok(patch)
+                    if patch.snapshot
+                        || patch.loaded().applies_to(content.len()) =>
+                {
+                    patch
+                }
+                _ => {
+                    self.write()?;
+                    return Err(SbvcError::PatchFailed(path));
+                }
+            };
+
+            let base = content.clone();
+            patch.apply(&mut content);
+
+            // Snapshots apply to any base, but delete all of the one they
+            // were made for
+            if patch.snapshot {
+                patch.set_difference(&base, &content);
+            }
+
+            if let Err(error) = self.pre_commit_hook.check(&content) {
+                self.write()?;
+                return Err(error);
+            }
+
+            // Signatures of patches were made for their original bases, so
+            // they are replaced
+            let signature = self.sign(&base, &content);
+            self.versions.push(Version {
+                id: self.next,
+                base: self.versions[self.current].id,
+                extra_parents: Vec::new(),
+                signature,
+                parent_hash: Some(hash(&base)),
+                checksum: Some(hash(&content)),
+                ..patch
+            });
+            self.next += 1;
+            self.current = self.versions.len() - 1;
+        }
+
+        self.write()
+    }
+
+    /// Returns the line-based difference between contents of versions with IDs
+    /// `from` and `to` grouped into hunks with `context` unchanged lines around
+    /// each change, similarly to unified diff.
+    ///
+    /// Lines keep their line terminators. Hunks whose context lines would
+    /// overlap or touch are merged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either of the versions is not found in the version
+    /// tree or if contents of either of them are not valid UTF-8.
+    pub fn text_diff_context(
+        &self,
+        from: u32,
+        to: u32,
+        context: usize,
+    ) -> SbvcResult<Vec<Hunk>> {
+        let from = self.data_by_id(from)?;
+        let to = self.data_by_id(to)?;
+        let old: Vec<_> =
+            str::from_utf8(&from)?.split_inclusive('\n').collect();
+        let new: Vec<_> = str::from_utf8(&to)?.split_inclusive('\n').collect();
+        let (mut old_index, mut new_index) = (0, 0);
+        let lines: Vec<_> = operations(&old, &new)
+            .into_iter()
+            .map(|operation| match operation {
+                Operation::Equal => {
+                    old_index += 1;
+                    new_index += 1;
+                    DiffLine::Context(old[old_index - 1].to_string())
+                }
+                Operation::Deletion => {
+                    old_index += 1;
+                    DiffLine::Deletion(old[old_index - 1].to_string())
+                }
+                Operation::Insertion => {
+                    new_index += 1;
+                    DiffLine::Insertion(new[new_index - 1].to_string())
+                }
+            })
+            .collect();
+
+        let changes: Vec<_> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, DiffLine::Context(_)))
+            .map(|(index, _)| index)
+            .collect();
+
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+
+        for index in changes {
+            let start = index.saturating_sub(context);
+            let end = (index + context + 1).min(lines.len());
+
+            match ranges.last_mut() {
+                Some(range) if start <= range.end => range.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+
+        let mut hunks = Vec::new();
+        let (mut old_start, mut new_start, mut index) = (0, 0, 0);
+
+        for range in ranges {
+            for line in &lines[index..range.start] {
+                match line {
+                    DiffLine::Context(_) => {
+                        old_start += 1;
+                        new_start += 1;
+                    }
+                    DiffLine::Deletion(_) => old_start += 1,
+                    DiffLine::Insertion(_) => new_start += 1,
+                }
+            }
+
+            let hunk_lines = lines[range.clone()].to_vec();
+            let old_len = hunk_lines
+                .iter()
+                .filter(|line| !matches!(line, DiffLine::Insertion(_)))
+                .count();
+            let new_len = hunk_lines
+                .iter()
+                .filter(|line| !matches!(line, DiffLine::Deletion(_)))
+                .count();
+
+            hunks.push(Hunk {
+                old_start,
+                old_len,
+                new_start,
+                new_len,
+                lines: hunk_lines,
+            });
+
+            old_start += old_len;
+            new_start += new_len;
+            index = range.end;
+        }
+
+        Ok(hunks)
+    }
+
+    /// Returns the line-based difference between contents of versions with IDs
+    /// `from` and `to` in the unified diff format, with `context` unchanged
+    /// lines around each change.
+    ///
+    /// File headers contain names of the versions. Lines without a line
+    /// terminator at the end of contents are marked as in the output of
+    /// `diff`.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`text_diff_context`].
+    ///
+    /// [`text_diff_context`]: Sbvc::text_diff_context
+    pub fn unified_diff(
+        &self,
+        from: u32,
+        to: u32,
+        context: usize,
+    ) -> SbvcResult<String> {
+        let hunks = self.text_diff_context(from, to, context)?;
+        let name = |id| &self.versions[self.version(id).unwrap()].name;
+        let range = |start: usize, len: usize| match len {
+            0 => format!("{},0", start),
+            1 => format!("{}", start + 1),
+            _ => format!("{},{}", start + 1, len),
+        };
+        let mut diff = format!("--- {}\n+++ {}\n", name(from), name(to));
+
+        for hunk in hunks {
+            diff.push_str(&format!(
+                "@@ -{} +{} @@\n",
+                range(hunk.old_start, hunk.old_len),
+                range(hunk.new_start, hunk.new_len)
+            ));
+
+            for line in hunk.lines {
+                let (prefix, line) = match line {
+                    DiffLine::Context(line) => (' ', line),
+                    DiffLine::Deletion(line) => ('-', line),
+                    DiffLine::Insertion(line) => ('+', line),
+                };
+
+                diff.push(prefix);
+                diff.push_str(&line);
+
+                if !line.ends_with('\n') {
+                    diff.push_str("\n\\ No newline at end of file\n");
+                }
+            }
+        }
+
+        Ok(diff)
+    }
+
+    /// Returns byte ranges of contents of the version with ID `to` that differ
+    /// from contents of the version with ID `from`.
+    ///
+    /// Places where bytes were only deleted are represented by empty ranges.
+    /// Adjacent ranges are merged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either of the versions is not found in the version
+    /// tree.
+    pub fn changed_ranges(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> SbvcResult<Vec<Range<usize>>> {
+        let from = self.data_by_id(from)?;
+        let to = self.data_by_id(to)?;
+
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        let mut index = 0;
+
+        for operation in operations(&from, &to) {
+            let range = match operation {
+                Operation::Equal => {
+                    index += 1;
+                    continue;
+                }
+                Operation::Deletion => index..index,
+                Operation::Insertion => {
+                    index += 1;
+                    index - 1..index
+                }
+            };
+
+            match ranges.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => ranges.push(range),
+            }
+        }
+
+        Ok(ranges)
+    }
+
+    /// Returns edits that turn contents of the version with ID `from` into
+    /// contents of the version with ID `to`, each with its byte range in both
+    /// contents, so that both can be shown side by side.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either of the versions is not found in the version
+    /// tree.
+    pub fn aligned_edits(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> SbvcResult<Vec<AlignedEdit>> {
+        let from = self.data_by_id(from)?;
+        let to = self.data_by_id(to)?;
+
+        let mut edits: Vec<AlignedEdit> = Vec::new();
+        let (mut old_index, mut new_index) = (0, 0);
+
+        for operation in operations(&from, &to) {
+            let (old_start, new_start) = (old_index, new_index);
+
+            match operation {
+                Operation::Equal => {
+                    old_index += 1;
+                    new_index += 1;
+                    continue;
+                }
+                Operation::Deletion => old_index += 1,
+                Operation::Insertion => new_index += 1,
+            }
+
+            match edits.last_mut() {
+                Some(last)
+                    if last.old_range.end == old_start
+                        && last.new_range.end == new_start =>
+                {
+                    last.old_range.end = old_index;
+                    last.new_range.end = new_index;
+                }
+                _ => edits.push(AlignedEdit {
+                    old_range: old_start..old_index,
+                    new_range: new_start..new_index,
+                }),
+            }
+        }
+
+        Ok(edits)
+    }
+
+    /// Returns deleted and inserted blocks of bytes that turn contents of the
+    /// version with ID `from` into contents of the version with ID `to`.
+    ///
+    /// If `detect_moves` is `true`, an inserted block equal to a deleted one
+    /// is reported together with it as a single [`BlockEdit::Move`]. This
+    /// does not affect how versions are stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either of the versions is not found in the version
+    /// tree.
+    ///
+    /// [`BlockEdit::Move`]: BlockEdit::Move
+    pub fn diff_between(
+        &self,
+        from: u32,
+        to: u32,
+        detect_moves: bool,
+    ) -> SbvcResult<Vec<BlockEdit>> {
+        self.with_data_pair(from, to, |from, to| {
+            block_edits(from, to, detect_moves)
+        })
+    }
+
+    /// Returns IDs of at most `n` versions with the largest stored differences,
+    /// largest first.
+    ///
+    /// See [`Version::stored_size`] for how sizes are measured.
+    ///
+    /// [`Version::stored_size`]: Version::stored_size
+    pub fn largest_versions(&self, n: usize) -> Vec<u32> {
+        let mut versions: Vec<_> = self.versions.iter().collect();
+        versions.sort_by_key(|version| cmp::Reverse(version.stored_size()));
+        versions.into_iter().take(n).map(|version| version.id).collect()
+    }
+
+    /// Returns the number of bytes inserted by differences of all versions.
+    ///
+    /// Contents of versions are not reconstructed, so this method is cheap.
+    pub fn total_inserted_bytes(&self) -> usize {
+        self.versions.iter().map(Version::inserted_size).sum()
+    }
+
+    /// Returns the number of bytes deleted by differences of all versions.
+    ///
+    /// Contents of versions are not reconstructed, so this method is cheap.
+    pub fn total_deleted_spans(&self) -> usize {
+        self.versions.iter().map(Version::deleted_size).sum()
+    }
+
+    /// Returns IDs of versions whose contents contain `needle`.
+    ///
+    /// If `ignore_case` is `true`, contents and `needle` are compared as UTF-8
+    /// text ignoring case, with invalid UTF-8 sequences replaced.
+    ///
+    /// This method reconstructs contents of every version, so it can be slow
+    /// for big version trees.
+    pub fn find_versions_containing(
+        &self,
+        needle: &[u8],
+        ignore_case: bool,
+    ) -> Vec<u32> {
+        let lowercase_needle = String::from_utf8_lossy(needle).to_lowercase();
+        let mut result = Vec::new();
+
+        self.for_each_content(|version, content| {
+            let contains = if ignore_case {
+                String::from_utf8_lossy(content)
+                    .to_lowercase()
+                    .contains(&lowercase_needle)
+            } else {
+                needle.is_empty()
+                    || content
+                        .windows(needle.len())
+                        .any(|window| window == needle)
+            };
+
+            if contains {
+                result.push(version.id);
+            }
+        });
+
+        result.sort_by_key(|&id| self.version(id));
+        result
+    }
+
+    /// Returns IDs of versions whose descriptions contain `substring`,
+    /// ordered by date and then by ID.
+    ///
+    /// Descriptions are the messages versions were committed with, see
+    /// [`commit_with_message`]. Versions without a description never match.
+    /// If `ignore_case` is `true`, case is ignored.
+    ///
+    /// [`commit_with_message`]: Sbvc::commit_with_message
+    pub fn find_by_message(
+        &self,
+        substring: &str,
+        ignore_case: bool,
+    ) -> Vec<u32> {
+        let substring = if ignore_case {
+            substring.to_lowercase()
+        } else {
+            substring.to_string()
+        };
+        let mut versions: Vec<_> = self
+            .versions
+            .iter()
+            .filter(|version| {
+                !version.description.is_empty()
+                    && if ignore_case {
+                        version.description.to_lowercase().contains(&substring)
+                    } else {
+                        version.description.contains(&substring)
+                    }
+            })
+            .collect();
+        versions.sort_by_key(|version| (version.date, version.id));
+        versions.into_iter().map(|version| version.id).collect()
+    }
+
+    /// Returns the ID of the earliest version, by date and then by ID, whose
+    /// contents are equal to `content`, or `None` if there is no such version.
+    ///
+    /// Versions whose stored checksums differ from the checksum of `content`
+    /// are skipped, so only contents of the remaining ones are reconstructed
+    /// and compared.
+    pub fn first_version_with_content(&self, content: &[u8]) -> Option<u32> {
+        let checksum = hash(content);
+        let mut candidates: Vec<_> = self
+            .versions
+            .iter()
+            .filter(|version| {
+                version.checksum.as_ref().is_none_or(|other| *other == checksum)
+            })
+            .collect();
+        candidates.sort_by_key(|version| (version.date, version.id));
+
+        candidates
+            .into_iter()
+            .find(|version| self.data(version) == content)
+            .map(|version| version.id)
+    }
+
+    /// Returns `true` if contents of the version with ID `id` are the same as
+    /// contents of the version with ID `other_id` in the version tree `other`.
+    ///
+    /// Only contents are compared, so the version trees may track different
+    /// files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either of the versions is not found in its version
+    /// tree.
+    pub fn equals_version(
+        &self,
+        id: u32,
+        other: &Sbvc,
+        other_id: u32,
+    ) -> SbvcResult<bool> {
+        Ok(self.data_by_id(id)? == other.data_by_id(other_id)?)
+    }
+
+    /// Returns the similarity of contents of versions with IDs `from` and `to`
+    /// as the fraction of bytes that are unchanged between them relative to
+    /// the size of the larger content.
+    ///
+    /// Returns `1.0` for identical contents, including two empty ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either of the versions is not found in the version
+    /// tree.
+    pub fn similarity(&self, from: u32, to: u32) -> SbvcResult<f64> {
+        let from = self.data_by_id(from)?;
+        let to = self.data_by_id(to)?;
+        let len = from.len().max(to.len());
+
+        if len == 0 {
+            return Ok(1.0);
+        }
+
+        let unchanged = operations(&from, &to)
+            .into_iter()
+            .filter(|operation| matches!(operation, Operation::Equal))
+            .count();
+
+        Ok(unchanged as f64 / len as f64)
+    }
+
+    /// Returns the number of bytes that have to be inserted plus the number of
+    /// bytes that have to be deleted to turn contents of the version with ID
+    /// `from` into contents of the version with ID `to`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either of the versions is not found in the version
+    /// tree.
+    pub fn edit_distance(&self, from: u32, to: u32) -> SbvcResult<usize> {
+        let from = self.data_by_id(from)?;
+        let to = self.data_by_id(to)?;
+
+        Ok(operations(&from, &to)
+            .into_iter()
+            .filter(|operation| !matches!(operation, Operation::Equal))
+            .count())
+    }
+
+    /// Returns the difference that transforms contents of the version with ID
+    /// `from` into contents of the version with ID `to`, computed the same way
+    /// as differences of versions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either of the versions is not found in the version
+    /// tree.
+    pub fn diff_versions(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> SbvcResult<OwnedDifference<u8>> {
+        let from = self.data_by_id(from)?;
+        Ok(self.data_by_id(to)?.diff(&from).to_owned())
+    }
+
+    /// Returns the difference that transforms contents of file `a` into
+    /// contents of file `b`, computed the same way as differences of versions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs.
+    pub fn diff_files(a: &Path, b: &Path) -> SbvcResult<OwnedDifference<u8>> {
+        Ok(fs::read(b)?.diff(&fs::read(a)?).to_owned())
+    }
+
+    /// Compares this version tree with another one, matching versions by
+    /// their contents.
+    ///
+    /// Each version is matched with at most one version of the other tree.
+    pub fn tree_diff(&self, other: &Sbvc) -> TreeDiff {
+        let mut other_hashes: Vec<_> = other
+            .versions
+            .iter()
+            .map(|version| Some((version.id, hash(&other.data(version)))))
+            .collect();
+        let mut tree_diff = TreeDiff {
+            only_self: Vec::new(),
+            only_other: Vec::new(),
+            common: Vec::new(),
+        };
+
+        for version in &self.versions {
+            let hash = hash(&self.data(version));
+
+            match other_hashes.iter_mut().find(|other_hash| {
+                other_hash.as_ref().is_some_and(|(_, other)| *other == hash)
+            }) {
+                Some(other_hash) => tree_diff
+                    .common
+                    .push((version.id, other_hash.take().unwrap().0)),
+                None => tree_diff.only_self.push(version.id),
+            }
+        }
+
+        tree_diff
+            .only_other
+            .extend(other_hashes.into_iter().flatten().map(|(id, _)| id));
+        tree_diff
+    }
+
+    /// Checks that contents of the version with the specified ID are
+    /// reconstructed the same way by an independent, bounds-checked replay of
+    /// every difference from the initial version and by the normal replay
+    /// from the nearest snapshot, or from the initial version if there are no
+    /// snapshots on the way.
+    ///
+    /// This is useful for debugging reconstruction issues.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree. If
+    /// the difference of some version does not apply to its base, or the
+    /// reconstructions differ, returns [`SbvcError::InvalidFormat`] naming the
+    /// version and, in the latter case, the first differing offset.
+    ///
+    /// [`SbvcError::InvalidFormat`]: SbvcError::InvalidFormat
+    pub fn verify_reconstruction(&self, id: u32) -> SbvcResult<()> {
+        let mut chain = self.ancestors(id, false)?;
+        chain.reverse();
+        chain.push(id);
+
+        let chain: Vec<_> = chain
+            .into_iter()
+            .map(|id| &self.versions[self.version(id).unwrap()])
+            .collect();
+        let mut naive = Vec::new();
+
+        for version in &chain[1..] {
+            let difference = version.loaded();
+
+            if !difference.applies_to(naive.len()) {
+                return Err(SbvcError::InvalidFormat(format!(
+                    "Difference of version with ID {} does not apply to its \
+                    base",
+                    version.id
+                )));
+            }
+
+            let mut patched: Vec<_> = naive
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| {
+                    !difference
+                        .deletions
+                        .iter()
+                        .any(|deletion| deletion.contains(index))
+                })
+                .map(|(_, &byte)| byte)
+                .collect();
+
+            for insertion in &difference.insertions {
+                let tail = patched.split_off(insertion.start);
+                patched.extend_from_slice(&insertion.data);
+                patched.extend(tail);
+            }
+
+            naive = patched;
+        }
+
+        // Unlike `data`, this does not use cached contents
+        let start =
+            chain.iter().rposition(|version| version.snapshot).unwrap_or(0);
+        let mut content = Vec::new();
+
+        for version in &chain[start..] {
+            version.apply(&mut content);
+        }
+
+        if naive != content {
+            let offset = naive
+                .iter()
+                .zip(&content)
+                .position(|(left, right)| left != right)
+                .unwrap_or_else(|| naive.len().min(content.len()));
+
+            return Err(SbvcError::InvalidFormat(format!(
+                "Reconstructions of version with ID {} differ at offset {}",
+                id, offset
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn for_each_content(&self, mut f: impl FnMut(&Version, &[u8])) {
+        let mut stack: Vec<_> = self
+            .versions
+            .iter()
+            .filter(|version| version.id == version.base)
+            .map(|version| (version, Vec::new()))
+            .collect();
+
+        while let Some((version, content)) = stack.pop() {
+            f(version, &content);
+
+            for child in self.children_of(version.id) {
+                let mut content = content.clone();
+                child.apply(&mut content);
+                stack.push((child, content));
+            }
+        }
+    }
+
+    /// Returns the ID of the nearest ancestor of the version with the
+    /// specified ID that has more than one child, that is, the point where the
+    /// branch of the version diverged. If there is no such ancestor, returns
+    /// the ID of the initial version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn branch_point_of(&self, id: u32) -> SbvcResult<u32> {
+        let ancestors = self.ancestors(id, false)?;
+
+        Ok(ancestors
+            .iter()
+            .copied()
+            .find(|&ancestor| self.children_of(ancestor).nth(1).is_some())
+            .or_else(|| ancestors.last().copied())
+            .unwrap_or(id))
+    }
+
+    /// Returns the number of versions branching from the version with the
+    /// specified ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn child_count(&self, id: u32) -> SbvcResult<usize> {
+        self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+        Ok(self.children_of(id).count())
+    }
+
+    /// Returns versions branching from the version with the specified ID, in
+    /// the order they were committed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn children(&self, id: u32) -> SbvcResult<Vec<&Version>> {
+        self.version(id).ok_or(SbvcError::VersionNotFound(id))?;
+        Ok(self.children_of(id).collect())
+    }
+
+    /// Returns versions no other version branches from, that is, tips of all
+    /// branches.
+    pub fn leaves(&self) -> Vec<&Version> {
+        let bases: HashSet<_> = self
+            .versions
+            .iter()
+            .filter(|version| version.id != version.base)
+            .map(|version| version.base)
+            .collect();
+
+        self.versions
+            .iter()
+            .filter(|version| !bases.contains(&version.id))
+            .collect()
+    }
+
+    /// Sets the key used to sign new versions, or disables signing if `key` is
+    /// `None`.
+    ///
+    /// While a key is set, every version created or rebased by this instance
+    /// stores an HMAC-SHA256 of its contents and of the hash of contents of its
+    /// base version, which can later be checked with [`verify_signatures`].
+    /// The key itself is never saved.
+    ///
+    /// [`verify_signatures`]: Sbvc::verify_signatures
+    pub fn set_signing_key(&mut self, key: Option<Vec<u8>>) {
+        self.signing_key = key;
+    }
+
+    /// Returns IDs of signed versions whose signatures do not match their
+    /// contents under the current signing key, e.g. because the version tree
+    /// file was tampered with.
+    ///
+    /// Unsigned versions are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no signing key is set.
+    pub fn verify_signatures(&self) -> SbvcResult<Vec<u32>> {
+        let key = self.signing_key.as_deref().ok_or_else(|| {
+            SbvcError::InvalidOperation("No signing key set".to_string())
+        })?;
+        let mut hashes: HashMap<u32, Vec<u8>> = HashMap::new();
+        let mut invalid = Vec::new();
+
+        self.for_each_content(|version, content| {
+            if let Some(signature) = &version.signature {
+                let valid = hashes.get(&version.base).is_some_and(|base| {
+                    signer(key, base, content).verify_slice(signature).is_ok()
+                });
+
+                if !valid {
+                    invalid.push(version.id);
+                }
+            }
+
+            hashes.insert(version.id, Sha256::digest(content).to_vec());
+        });
+
+        invalid.sort();
+        Ok(invalid)
+    }
+
+    fn sign(&self, base: &[u8], content: &[u8]) -> Option<Vec<u8>> {
+        self.signing_key.as_deref().map(|key| {
+            signer(key, &Sha256::digest(base), content)
+                .finalize()
+                .into_bytes()
+                .to_vec()
+        })
+    }
+
+    /// Returns the 1-based position of the version with the specified ID when
+    /// all versions are ordered by date and then by ID, e.g. for display.
+    ///
+    /// Unlike IDs, ordinals are dense, so they shift when versions are
+    /// deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not found in the version tree.
+    pub fn ordinal(&self, id: u32) -> SbvcResult<usize> {
+        let version = &self.versions
+            [self.version(id).ok_or(SbvcError::VersionNotFound(id))?];
+        let key = (version.date, version.id);
+
+        Ok(1 + self
+            .versions
+            .iter()
+            .filter(|version| (version.date, version.id) < key)
+            .count())
+    }
+
+    /// Returns the version tree in the DOT format of Graphviz.
+    ///
+    /// Every version is a node labeled with its ID and name, with an edge from
+    /// its base version and dashed edges from its extra parents. If `weighted`
+    /// is `true`, edges from base versions are labeled with
+    /// [`Version::stored_size`] and are drawn thicker for bigger changes.
+    pub fn to_dot(&self, weighted: bool) -> String {
+        let mut dot = String::from("digraph sbvc {\n");
+
+        for version in &self.versions {
+            let label = format!("{}: {}", version.id, version.name)
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"");
+            dot.push_str(&format!(
+                "    {} [label=\"{}\"];\n",
+                version.id, label
+            ));
+        }
+
+        for version in &self.versions {
+            if version.id != version.base {
+                if weighted {
+                    let size = version.stored_size();
+                    dot.push_str(&format!(
+                        "    {} -> {} [label=\"{}\", penwidth={:.2}];\n",
+                        version.base,
+                        version.id,
+                        size,
+                        1.0 + (size as f64 + 1.0).log2() / 4.0,
+                    ));
+                } else {
+                    dot.push_str(&format!(
+                        "    {} -> {};\n",
+                        version.base, version.id
+                    ));
+                }
+            }
+
+            for parent in &version.extra_parents {
+                dot.push_str(&format!(
+                    "    {} -> {} [style=dashed];\n",
+                    parent, version.id
+                ));
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn children_of(&self, id: u32) -> impl Iterator<Item = &Version> {
+        self.versions
+            .iter()
+            .filter(move |version| version.base == id && version.id != id)
+    }
+
+    fn version(&self, id: u32) -> Option<usize> {
+        self.versions
+            .iter()
+            .enumerate()
+            .find(|&(_, version)| version.id == id)
+            .map(|(index, _)| index)
+    }
+
+    /// Returns a slice of all versions.
+    pub fn versions(&self) -> &[Version] {
+        &self.versions
+    }
+}
+
+fn difference(content: &[u8], base: &[u8]) -> (OwnedDifference<u8>, bool) {
+    // The previous contents are empty for the first commit after the initial
+    // version, so there is nothing to diff against
+    if base.is_empty() {
+        return (full_insertion(content), false);
+    }
+
+    // Diffing bytes takes time proportional to the product of the sizes, so
+    // big contents are diffed by chunks instead
+    let difference = if content.len().max(base.len()) > CHUNKED_DIFF_THRESHOLD {
+        chunked_difference(content, base)
+    } else {
+        content.diff(base).to_owned()
+    };
+    let size = difference
+        .insertions
+        .iter()
+        .map(|insertion| insertion.data.len())
+        .chain(difference.deletions.iter().map(|deletion| deletion.len()))
+        .sum::<usize>();
+
+    // Snapshots still delete all of the base, so that readers unaware of them
+    // reconstruct the same contents
+    if size > content.len() {
+        let mut difference = full_insertion(content);
+        difference.deletions.push(0..base.len());
+        (difference, true)
+    } else {
+        (difference, false)
+    }
+}
+
+// Same as diffing bytes, but only whole chunks are matched, so the difference
+// can be larger
+fn chunked_difference(content: &[u8], base: &[u8]) -> OwnedDifference<u8> {
+    let old = chunks(base);
+    let new = chunks(content);
+    let mut difference = OwnedDifference::empty();
+    let (mut old_index, mut new_index) = (0, 0);
+    let (mut old_start, mut new_start) = (0, 0);
+
+    for operation in operations(&old, &new) {
+        match operation {
+            Operation::Equal => {
+                old_start += old[old_index].len();
+                new_start += new[new_index].len();
+                old_index += 1;
+                new_index += 1;
+            }
+            Operation::Deletion => {
+                let end = old_start + old[old_index].len();
+
+                match difference.deletions.last_mut() {
+                    Some(last) if last.end == old_start => last.end = end,
+                    _ => difference.deletions.push(old_start..end),
+                }
+
+                old_start = end;
+                old_index += 1;
+            }
+            Operation::Insertion => {
+                let chunk = new[new_index];
+
+                match difference.insertions.last_mut() {
+                    Some(last) if last.start + last.data.len() == new_start => {
+                        last.data.extend_from_slice(chunk);
+                    }
+                    _ => difference
+                        .insertions
+                        .push(OwnedInsertion::new(new_start, chunk.to_vec())),
+                }
+
+                new_start += chunk.len();
+                new_index += 1;
+            }
+        }
+    }
+
+    difference
+}
+
+// Splits data into chunks whose boundaries depend only on the bytes around
+// them, so that an edit only changes the chunks it touches
+fn chunks(data: &[u8]) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut hash = 0u64;
+
+    for (index, &byte) in data.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        let len = index + 1 - start;
+
+        if (len >= CHUNK_MIN && hash & CHUNK_MASK == 0) || len >= CHUNK_MAX {
+            chunks.push(&data[start..=index]);
+            start = index + 1;
+            hash = 0;
+        }
+    }
+
+    if start < data.len() {
+        chunks.push(&data[start..]);
+    }
+
+    chunks
+}
+
+// Pseudorandom values for the rolling hash used by `chunks`
+const fn gear() -> [u64; 256] {
+    let mut gear = [0; 256];
+    let mut state = 0u64;
+    let mut index = 0;
+
+    while index < gear.len() {
+        // SplitMix64
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut value = state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+        gear[index] = value ^ (value >> 31);
+        index += 1;
+    }
+
+    gear
+}
+
+fn signer(key: &[u8], base_hash: &[u8], content: &[u8]) -> Hmac<Sha256> {
+    // HMAC accepts keys of any length
+    let mut signer = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    signer.update(base_hash);
+    signer.update(content);
+    signer
+}
+
+fn full_insertion(content: &[u8]) -> OwnedDifference<u8> {
+    let mut difference = OwnedDifference::empty();
+
+    if !content.is_empty() {
+        difference.insertions.push(OwnedInsertion::new(0, content.to_vec()));
+    }
+
+    difference
+}
+
+fn check_self_tracking(path: &Path, file: &Path) -> SbvcResult<()> {
+    let same = path == file
+        || matches!(
+            (fs::canonicalize(path), fs::canonicalize(file)),
+            (Ok(path), Ok(file)) if path == file
+        );
+
+    if same {
+        Err(SbvcError::SelfTracking)
+    } else {
+        Ok(())
+    }
+}
+
+struct Header<'a> {
+    file: PathBuf,
+    current_id: u32,
+    next: u32,
+    list: &'a [u8],
+    compression: bool,
+    blobs: Option<&'a [u8]>,
+}
+
+fn parse_header(source: &[u8], lenient: bool) -> SbvcResult<Header<'_>> {
+    let mut iter = NelfIter::from_string(source);
+    let (file, current_id, next) = parse_header_start(&mut iter)?;
+
+    let list = iter.next().or(lenient.then_some(&[][..])).ok_or_else(|| {
+        SbvcError::InvalidFormat("Expected list of versions".to_string())
+    })?;
+
+    let compression = iter.next() == Some(b"1");
+    let blobs = iter.next();
+
+    Ok(Header { file, current_id, next, list, compression, blobs })
+}
+
+type Blobs<'a> = HashMap<&'a [u8], &'a [u8]>;
+
+fn parse_blobs(source: &[u8]) -> SbvcResult<Blobs<'_>> {
+    NelfIter::from_string(source)
+        .map(|blob| {
+            let mut iter = NelfIter::from_string(blob);
+            let key = iter.next().ok_or_else(|| {
+                SbvcError::InvalidFormat("Expected blob key".to_string())
+            })?;
+            let data = iter.next().ok_or_else(|| {
+                SbvcError::InvalidFormat("Expected blob data".to_string())
+            })?;
+            Ok((key, data))
+        })
+        .collect()
+}
+
+// Key insertions with these contents are stored under when deduplicating
+fn blob_key(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Parses the part of the header before the list of versions
+fn parse_header_start<'a>(
+    iter: &mut impl Iterator<Item = &'a [u8]>,
+) -> SbvcResult<(PathBuf, u32, u32)> {
+    let file = path_from_bytes(
+        iter.next().ok_or_else(|| {
+            SbvcError::InvalidFormat("Expected filename".to_string())
+        })?,
+        || "filename".to_string(),
+    )?;
+
+    let current_id = str::from_utf8(iter.next().ok_or_else(|| {
+        SbvcError::InvalidFormat("Expected current version ID".to_string())
+    })?)?
+    .parse()?;
+
+    let next = str::from_utf8(iter.next().ok_or_else(|| {
+        SbvcError::InvalidFormat("Expected next version ID".to_string())
+    })?)?
+    .parse()?;
+
+    Ok((file, current_id, next))
+}
+
+// Content of a cell of a NELF list read from a stream of bytes, following the
+// same rules as `NelfIter`, so that big cells can be checked without holding
+// them in memory
+struct CellBytes<I> {
+    bytes: I,
+    first: Option<u8>,
+    len: usize,
+    rch: u8,
+    // Closing characters read so far, which are part of the content if fewer
+    // than `len` of them follow each other
+    count: usize,
+    flush: usize,
+    held: Option<u8>,
+    done: bool,
+}
+
+impl<I: Iterator<Item = io::Result<u8>>> Iterator for CellBytes<I> {
+    type Item = io::Result<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.flush > 0 {
+            self.flush -= 1;
+            return Some(Ok(self.rch));
+        }
+
+        if let Some(ch) = self.held.take() {
+            return Some(Ok(ch));
+        }
+
+        while !self.done && self.count < self.len {
+            let ch =
+                match self.first.take().map(Ok).or_else(|| self.bytes.next()) {
+                    Some(Ok(ch)) => ch,
+                    Some(Err(error)) => return Some(Err(error)),
+                    None => break,
+                };
+
+            if ch == self.rch {
+                self.count += 1;
+            } else if self.count > 0 {
+                self.flush = self.count - 1;
+                self.count = 0;
+                self.held = Some(ch);
+                return Some(Ok(self.rch));
+            } else {
+                return Some(Ok(ch));
+            }
+        }
+
+        self.done = true;
+
+        // Closing characters of an unterminated cell are its content
+        if self.count > 0 && self.count < self.len {
+            self.flush = self.count - 1;
+            self.count = 0;
+            return Some(Ok(self.rch));
+        }
+
+        None
+    }
+}
+
+// Reads the opening delimiter of the next cell in `bytes`, if there is one
+fn next_cell<I: Iterator<Item = io::Result<u8>>>(
+    mut bytes: I,
+) -> io::Result<Option<CellBytes<I>>> {
+    let lch = loop {
+        match bytes.next().transpose()? {
+            Some(ch @ (b'|' | b'/' | b'\\')) => break ch,
+            Some(_) => {}
+            None => return Ok(None),
+        }
+    };
+
+    let mut len = 1;
+
+    let first = loop {
+        match bytes.next().transpose()? {
+            Some(ch) if ch == lch => len += 1,
+            Some(ch) => break ch,
+            None => return Ok(None),
+        }
+    };
+
+    let rch = match lch {
+        b'|' => b'|',
+        b'/' => b'\\',
+        _ => b'/',
+    };
+
+    Ok(Some(CellBytes {
+        bytes,
+        first: Some(first),
+        len,
+        rch,
+        count: 0,
+        flush: 0,
+        held: None,
+        done: false,
+    }))
+}
+
+fn skip_cell(mut cell: impl Iterator<Item = io::Result<u8>>) -> io::Result<()> {
+    cell.try_for_each(|ch| ch.map(drop))
+}
+
+fn hash_cell(
+    cell: impl Iterator<Item = io::Result<u8>>,
+) -> io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+
+    for ch in cell {
+        hasher.update([ch?]);
+    }
+
+    Ok(hasher.finalize().to_vec())
+}
+
+// Parses the version in `cell` with its inserted bytes replaced by nothing,
+// returning it with hashes of the replaced bytes
+fn skim_version<I: Iterator<Item = io::Result<u8>>>(
+    mut cell: CellBytes<I>,
+) -> SbvcResult<(Version, Vec<Vec<u8>>)> {
+    let mut fields = Vec::new();
+    let mut hashes = Vec::new();
+
+    while let Some(mut field) = next_cell(&mut cell)? {
+        // Fields before the insertions are metadata, which is kept
+        if fields.len() != 4 {
+            fields.push(field.collect::<io::Result<Vec<_>>>()?);
+            continue;
+        }
+
+        let mut insertions = Vec::new();
+
+        while let Some(mut insertion) = next_cell(&mut field)? {
+            let mut parts = Vec::new();
+
+            if let Some(start) = next_cell(&mut insertion)? {
+                parts.push(start.collect::<io::Result<Vec<_>>>()?);
+            }
+
+            if let Some(data) = next_cell(&mut insertion)? {
+                hashes.push(hash_cell(data)?);
+                parts.push(Vec::new());
+            }
+
+            skip_cell(insertion)?;
+            insertions.push(parts.to_newline_nelf());
+        }
+
+        fields.push(insertions.to_newline_nelf());
+    }
+
+    let version = Version::from_nelf_private(
+        &fields.to_newline_nelf(),
+        Payload::Skip,
+        None,
+    )?;
+    Ok((version, hashes))
+}
+
+// Index of a version tree file: its hash, length and modification time, the
+// length and the hash of the header before the list of versions, whether it
+// is compressed and deduplicated, and every version with its inserted bytes
+// replaced by their ranges in the file
+fn index(
+    source: &[u8],
+    versions: &[Version],
+    modified: SystemTime,
+) -> Option<Vec<u8>> {
+    let offset =
+        |cell: &[u8]| cell.as_ptr() as usize - source.as_ptr() as usize;
+    let header = parse_header(source, false).ok()?;
+    let blobs = header.blobs.map(parse_blobs).transpose().ok()?;
+    let entries = NelfIter::from_string(header.list)
+        .zip(versions)
+        .map(|(cell, version)| {
+            let keys =
+                NelfIter::from_string(NelfIter::from_string(cell).nth(4)?)
+                    .map(|insertion| {
+                        let mut data =
+                            NelfIter::from_string(insertion).nth(1)?;
+
+                        if let Some(blobs) = &blobs {
+                            data = blobs.get(data)?;
+                        }
+
+                        let start = offset(data);
+                        Some(format!("{}-{}", start, start + data.len()))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+            Some(version.to_nelf_private(false, Some(&keys)))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let header_len = offset(header.list);
+    let modified = modified.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+
+    Some(
+        [
+            hash(source).as_bytes(),
+            source.len().to_string().as_bytes(),
+            modified.as_nanos().to_string().as_bytes(),
+            header_len.to_string().as_bytes(),
+            hash(&source[..header_len]).as_bytes(),
+            if header.compression { b"1" } else { b"0" },
+            if blobs.is_some() { b"1" } else { b"0" },
+            &entries.to_newline_nelf(),
+        ]
+        .to_newline_nelf(),
+    )
+}
+
+// Parts of a version tree file needed to construct a `Sbvc` instance
+struct Parsed {
+    file: PathBuf,
+    current_id: u32,
+    next: u32,
+    versions: Vec<Version>,
+    source_hash: String,
+    compression: bool,
+    deduplication: bool,
+}
+
+fn parse_versions<'a>(
+    cells: impl Iterator<Item = &'a [u8]>,
+    payload: Payload,
+    blobs: Option<&Blobs>,
+    limits: &Limits,
+) -> SbvcResult<Vec<Version>> {
+    let mut versions = Vec::new();
+
+    for cell in cells {
+        if versions.len() == limits.max_versions {
+            return Err(SbvcError::TooLarge(format!(
+                "Version tree contains more than {} versions",
+                limits.max_versions
+            )));
+        }
+
+        versions.push(Version::from_nelf_private(cell, payload, blobs)?);
+    }
+
+    Ok(versions)
+}
+
+struct Index {
+    source_hash: String,
+    len: u64,
+    header_len: usize,
+    header_hash: String,
+    compression: bool,
+    deduplication: bool,
+    versions: Vec<u8>,
+}
+
+// Returns `None` if the index is malformed or was made for a file with a
+// different length or modification time
+fn parse_index(index: &[u8], len: u64, modified: SystemTime) -> Option<Index> {
+    let mut iter = NelfIter::from_string(index);
+    let mut field = || str::from_utf8(iter.next()?).ok();
+    let source_hash = field()?.to_string();
+    let modified = modified.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+
+    if field()?.parse::<u64>().ok()? != len
+        || field()?.parse::<u128>().ok()? != modified.as_nanos()
+    {
+        return None;
+    }
+
+    Some(Index {
+        source_hash,
+        len,
+        header_len: field()?.parse().ok()?,
+        header_hash: field()?.to_string(),
+        compression: field()? == "1",
+        deduplication: field()? == "1",
+        versions: iter.next()?.to_vec(),
+    })
+}
+
+#[cfg(feature = "compression")]
+fn deflate(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec(data, 6)
+}
+
+// Compression can't be turned on without the feature
+#[cfg(not(feature = "compression"))]
+fn deflate(_: &[u8]) -> Vec<u8> {
+    unreachable!()
+}
+
+#[cfg(feature = "compression")]
+fn inflate(data: &[u8]) -> SbvcResult<Vec<u8>> {
+    miniz_oxide::inflate::decompress_to_vec(data).map_err(|_| {
+        SbvcError::InvalidFormat(
+            "Invalid compressed insertion data".to_string(),
+        )
+    })
+}
+
+#[cfg(not(feature = "compression"))]
+fn inflate(_: &[u8]) -> SbvcResult<Vec<u8>> {
+    Err(SbvcError::InvalidFormat(
+        "Insertions are compressed, which requires the `compression` feature"
+            .to_string(),
+    ))
+}
+
+// Checks that following bases from every version leads to an initial version,
+// given IDs of versions with IDs of their bases
+fn check_bases(versions: &[(u32, u32)]) -> SbvcResult<()> {
+    let bases: HashMap<_, _> = versions.iter().copied().collect();
+    let mut checked = HashSet::new();
+
+    for &(start, _) in versions {
+        let mut path = HashSet::new();
+        let mut id = start;
+
+        while !checked.contains(&id) {
+            if !path.insert(id) {
+                return Err(SbvcError::InvalidFormat(format!(
+                    "Base versions of version with ID {} form a cycle",
+                    start
+                )));
+            }
+
+            let base = bases[&id];
+
+            if base == id {
+                break;
+            }
+
+            if !bases.contains_key(&base) {
+                return Err(SbvcError::InvalidFormat(format!(
+                    "Base version with ID {} of version with ID {} not found",
+                    base, id
+                )));
+            }
+
+            id = base;
+        }
+
+        checked.extend(path);
+    }
+
+    Ok(())
+}
+
+// Checks that the difference of every version is within bounds of contents of
+// its base, so that reconstructing contents never fails. Bases must be checked
+// with `check_bases` first
+fn check_bounds(versions: &[Version]) -> SbvcResult<()> {
+    let indices: HashMap<_, _> = versions
+        .iter()
+        .enumerate()
+        .map(|(index, version)| (version.id, index))
+        .collect();
+    let mut lens = HashMap::new();
+
+    for version in versions {
+        let mut chain = Vec::new();
+        let mut version = version;
+
+        // Lengths are computed starting from the nearest version with a known
+        // length
+        while !lens.contains_key(&version.id) {
+            chain.push(version);
+
+            if version.id == version.base {
+                break;
+            }
+
+            version = &versions[indices[&version.base]];
+        }
+
+        for version in chain.into_iter().rev() {
+            let base = if version.id == version.base {
+                0
+            } else {
+                lens[&version.base]
+            };
+            let len = version.patched_len(base).map_err(|offset| {
+                SbvcError::InvalidFormat(format!(
+                    "Difference of version with ID {} is out of bounds of its \
+                    base at offset {}",
+                    version.id, offset
+                ))
+            })?;
+            lens.insert(version.id, len);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+thread_local! {
+    // Number of times files were synced on this thread, to test durability
+    static SYNCS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    // Number of times contents of versions were reconstructed or copied from
+    // the cache on this thread
+    static DATA_CALLS: std::cell::Cell<usize> =
+        const { std::cell::Cell::new(0) };
+}
+
+fn sync(file: &fs::File) -> io::Result<()> {
+    #[cfg(test)]
+    SYNCS.with(|syncs| syncs.set(syncs.get() + 1));
+    file.sync_all()
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+// Parses ranges written by `index` as `<start>-<end>`
+fn parse_range(source: &[u8]) -> Option<Range<usize>> {
+    let (start, end) = str::from_utf8(source).ok()?.split_once('-')?;
+    let range = start.parse().ok()?..end.parse().ok()?;
+    (range.start <= range.end).then_some(range)
+}
+
+fn with_suffix(file: &Path, suffix: &str) -> PathBuf {
+    let mut path = file.as_os_str().to_os_string();
+    path.push(suffix);
+    path.into()
+}
+
+fn parse_hex(source: &[u8]) -> Option<Vec<u8>> {
+    if !source.len().is_multiple_of(2) {
+        return None;
+    }
+
+    source
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+// Paths are stored as raw bytes on Unix, so that any path survives a round
+// trip. Elsewhere they are stored as UTF-8, replacing what cannot be encoded
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+        Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+    }
+}
+
+#[cfg(unix)]
+fn path_from_bytes(
+    source: &[u8],
+    _field: impl FnOnce() -> String,
+) -> SbvcResult<PathBuf> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    Ok(OsStr::from_bytes(source).into())
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(
+    source: &[u8],
+    field: impl FnOnce() -> String,
+) -> SbvcResult<PathBuf> {
+    Ok(utf8_field(source, field)?.into())
+}
+
+fn utf8_field(
+    source: &[u8],
+    field: impl FnOnce() -> String,
+) -> SbvcResult<&str> {
+    str::from_utf8(source).map_err(|error| SbvcError::Utf8Field(field(), error))
+}
+
+enum Operation {
+    Equal,
+    Deletion,
+    Insertion,
+}
+
+fn operations<T: Eq>(old: &[T], new: &[T]) -> Vec<Operation> {
+    let difference = new.diff(old);
+    let mut deleted = vec![false; old.len()];
+    let mut inserted = vec![false; new.len()];
+
+    for deletion in difference.deletions {
+        deleted[deletion].fill(true);
+    }
+
+    for insertion in difference.insertions {
+        inserted[insertion.start..insertion.start + insertion.data.len()]
+            .fill(true);
+    }
+
+    let mut operations = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+
+    while old_index < old.len() || new_index < new.len() {
+        if old_index < old.len() && deleted[old_index] {
+            operations.push(Operation::Deletion);
+            old_index += 1;
+        } else if new_index < new.len() && inserted[new_index] {
+            operations.push(Operation::Insertion);
+            new_index += 1;
+        } else {
+            operations.push(Operation::Equal);
+            old_index += 1;
+            new_index += 1;
+        }
+    }
+
+    operations
+}
+
+// Undoes changes that turned `base` into `target` in `content`, keeping changes
+// made to `content` since `target`
+fn reverted(base: &[u8], target: &[u8], content: &[u8]) -> Vec<u8> {
+    let mut introduced = vec![false; target.len()];
+    let mut removed = vec![Vec::new(); target.len() + 1];
+    let (mut target_index, mut base_index) = (0, 0);
+
+    for operation in operations(target, base) {
+        match operation {
+            Operation::Deletion => {
+                introduced[target_index] = true;
+                target_index += 1;
+            }
+            Operation::Insertion => {
+                removed[target_index].push(base[base_index]);
+                base_index += 1;
+            }
+            Operation::Equal => {
+                target_index += 1;
+                base_index += 1;
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    let (mut target_index, mut index) = (0, 0);
+
+    for operation in operations(target, content) {
+        match operation {
+            Operation::Deletion => {
+                result.append(&mut removed[target_index]);
+                target_index += 1;
+            }
+            Operation::Insertion => {
+                result.push(content[index]);
+                index += 1;
+            }
+            Operation::Equal => {
+                result.append(&mut removed[target_index]);
+
+                if !introduced[target_index] {
+                    result.push(content[index]);
+                }
+
+                target_index += 1;
+                index += 1;
+            }
+        }
+    }
+
+    result.append(&mut removed[target_index]);
+    result
+}
+
+// Changes of `new` compared to `old`, as ranges of `old` and their replacements
+// Blocks of bytes deleted from `from` and inserted into `to`, see
+// `Sbvc::diff_between`
+fn block_edits(from: &[u8], to: &[u8], detect_moves: bool) -> Vec<BlockEdit> {
+    let mut edits = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+
+    for operation in operations(from, to) {
+        match (operation, edits.last_mut()) {
+            (Operation::Equal, _) => {
+                old_index += 1;
+                new_index += 1;
+            }
+            (Operation::Deletion, Some(BlockEdit::Deletion(range)))
+                if range.end == old_index =>
+            {
+                old_index += 1;
+                range.end = old_index;
+            }
+            (Operation::Deletion, _) => {
+                edits.push(BlockEdit::Deletion(old_index..old_index + 1));
+                old_index += 1;
+            }
+            (Operation::Insertion, Some(BlockEdit::Insertion(range)))
+                if range.end == new_index =>
+            {
+                new_index += 1;
+                range.end = new_index;
+            }
+            (Operation::Insertion, _) => {
+                edits.push(BlockEdit::Insertion(new_index..new_index + 1));
+                new_index += 1;
+            }
+        }
+    }
+
+    if !detect_moves {
+        return edits;
+    }
+
+    let mut moved = vec![false; edits.len()];
+
+    for index in 0..edits.len() {
+        let BlockEdit::Insertion(to_range) = &edits[index] else {
+            continue;
+        };
+
+        let found = (0..edits.len()).find(|&other| {
+            matches!(
+                &edits[other],
+                BlockEdit::Deletion(from_range)
+                    if !moved[other]
+                        && from[from_range.clone()]
+                            == to[to_range.clone()]
+            )
+        });
+
+        if let Some(other) = found {
+            let BlockEdit::Deletion(from_range) = edits[other].clone() else {
+                unreachable!();
+            };
+
+            moved[other] = true;
+            edits[index] =
+                BlockEdit::Move { from_range, to_range: to_range.clone() };
+        }
+    }
+
+    edits
+        .into_iter()
+        .zip(moved)
+        .filter(|&(_, moved)| !moved)
+        .map(|(edit, _)| edit)
+        .collect()
+}
+
+fn hunks(old: &[u8], new: &[u8]) -> Vec<(Range<usize>, Vec<u8>)> {
+    let mut hunks: Vec<(Range<usize>, Vec<u8>)> = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+    let mut changing = false;
+
+    for operation in operations(old, new) {
+        if let Operation::Equal = operation {
+            changing = false;
+            old_index += 1;
+            new_index += 1;
+            continue;
+        }
+
+        if !changing {
+            hunks.push((old_index..old_index, Vec::new()));
+            changing = true;
+        }
+
+        let (range, replacement) = hunks.last_mut().unwrap();
+
+        if let Operation::Deletion = operation {
+            old_index += 1;
+            range.end = old_index;
+        } else {
+            replacement.push(new[new_index]);
+            new_index += 1;
+        }
+    }
+
+    hunks
+}
+
+// Three-way merge, returning clean parts of the result and conflicts between
+// them
+fn merged(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+) -> (Vec<Vec<u8>>, Vec<Conflict>) {
+    let our_hunks = hunks(base, ours);
+    let their_hunks = hunks(base, theirs);
+    let mut clean = vec![Vec::new()];
+    let mut conflicts = Vec::new();
+    let (mut our_index, mut their_index, mut position) = (0, 0, 0);
+
+    // Replays hunks over the range of the base
+    let replay = |hunks: &[(Range<usize>, Vec<u8>)], range: Range<usize>| {
+        let mut result = Vec::new();
+        let mut position = range.start;
+
+        for (hunk, replacement) in hunks {
+            result.extend_from_slice(&base[position..hunk.start]);
+            result.extend_from_slice(replacement);
+            position = hunk.end;
+        }
+
+        result.extend_from_slice(&base[position..range.end]);
+        result
+    };
+
+    loop {
+        let start =
+            match (our_hunks.get(our_index), their_hunks.get(their_index)) {
+                (None, None) => break,
+                (Some(ours), None) => ours.0.start,
+                (None, Some(theirs)) => theirs.0.start,
+                (Some(ours), Some(theirs)) => ours.0.start.min(theirs.0.start),
+            };
+
+        // Hunks that overlap or touch are merged as one
+        let (our_start, their_start) = (our_index, their_index);
+        let mut end = start;
+
+        loop {
+            if let Some(hunk) =
+                our_hunks.get(our_index).filter(|hunk| hunk.0.start <= end)
+            {
+                end = end.max(hunk.0.end);
+                our_index += 1;
+            } else if let Some(hunk) =
+                their_hunks.get(their_index).filter(|hunk| hunk.0.start <= end)
+            {
+                end = end.max(hunk.0.end);
+                their_index += 1;
+            } else {
+                break;
+            }
+        }
+
+        clean.last_mut().unwrap().extend_from_slice(&base[position..start]);
+        let ours = replay(&our_hunks[our_start..our_index], start..end);
+        let theirs = replay(&their_hunks[their_start..their_index], start..end);
+
+        if our_start == our_index {
+            clean.last_mut().unwrap().extend(theirs);
+        } else if their_start == their_index || ours == theirs {
+            clean.last_mut().unwrap().extend(ours);
+        } else {
+            conflicts.push(Conflict {
+                base_range: start..end,
+                ours,
+                theirs,
+                resolution: None,
+            });
+            clean.push(Vec::new());
+        }
+
+        position = end;
+    }
+
+    clean.last_mut().unwrap().extend_from_slice(&base[position..]);
+    (clean, conflicts)
+}
+
+fn hash(data: &[u8]) -> String {
+    let hash = data.iter().fold(0xcbf29ce484222325, |hash: u64, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+trait AppliesTo {
+    fn applies_to(&self, len: usize) -> bool;
+}
+
+impl AppliesTo for OwnedDifference<u8> {
+    fn applies_to(&self, mut len: usize) -> bool {
+        let mut end = 0;
+
+        for deletion in &self.deletions {
+            if deletion.start < end || deletion.end < deletion.start {
+                return false;
+            }
+
+            end = deletion.end;
+        }
+
+        if end > len {
+            return false;
+        }
+
+        len -=
+            self.deletions.iter().map(|deletion| deletion.len()).sum::<usize>();
+
+        for insertion in &self.insertions {
+            if insertion.start > len {
+                return false;
+            }
+
+            len += insertion.data.len();
+        }
+
+        true
+    }
+}
+
+trait ToNewlineNelf {
+    fn to_newline_nelf(self) -> Vec<u8>;
+}
+
+impl<T: IntoIterator<Item = V>, V: ToCell> ToNewlineNelf for T {
+    fn to_newline_nelf(self) -> Vec<u8> {
+        self.into_iter()
+            .flat_map(|string| {
+                let mut cell = string.to_cell();
+                cell.push(b'\n');
+                cell
+            })
+            .collect()
+    }
+}
+
+/// A group of nearby changed lines together with surrounding unchanged lines.
+///
+/// Returned by [`Sbvc::text_diff_context`].
+///
+/// [`Sbvc::text_diff_context`]: Sbvc::text_diff_context
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    old_start: usize,
+    old_len: usize,
+    new_start: usize,
+    new_len: usize,
+    lines: Vec<DiffLine>,
+}
+
+impl Hunk {
+    /// Returns the zero-based index of the first line of the hunk in the old
+    /// content.
+    pub fn old_start(&self) -> usize {
+        self.old_start
+    }
+
+    /// Returns the number of lines of the hunk in the old content.
+    pub fn old_len(&self) -> usize {
+        self.old_len
+    }
+
+    /// Returns the zero-based index of the first line of the hunk in the new
+    /// content.
+    pub fn new_start(&self) -> usize {
+        self.new_start
+    }
+
+    /// Returns the number of lines of the hunk in the new content.
+    pub fn new_len(&self) -> usize {
+        self.new_len
+    }
+
+    /// Returns the lines of the hunk.
+    pub fn lines(&self) -> &[DiffLine] {
+        &self.lines
+    }
+}
+
+/// A line of a [`Hunk`].
+///
+/// [`Hunk`]: Hunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    /// An unchanged line.
+    Context(String),
+    /// A line present only in the old content.
+    Deletion(String),
+    /// A line present only in the new content.
+    Insertion(String),
+}
+
+/// An edit between contents of two versions.
+///
+/// Returned by [`Sbvc::aligned_edits`].
+///
+/// [`Sbvc::aligned_edits`]: Sbvc::aligned_edits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignedEdit {
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+}
+
+impl AlignedEdit {
+    /// Returns the byte range of the edit in the old content.
+    pub fn old_range(&self) -> Range<usize> {
+        self.old_range.clone()
+    }
+
+    /// Returns the byte range of the edit in the new content.
+    pub fn new_range(&self) -> Range<usize> {
+        self.new_range.clone()
+    }
+
+    /// Returns the kind of the edit.
+    pub fn kind(&self) -> EditKind {
+        if self.new_range.is_empty() {
+            EditKind::Deletion
+        } else if self.old_range.is_empty() {
+            EditKind::Insertion
+        } else {
+            EditKind::Replacement
+        }
+    }
+}
+
+/// The kind of an [`AlignedEdit`].
+///
+/// [`AlignedEdit`]: AlignedEdit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    /// Bytes present only in the old content.
+    Deletion,
+    /// Bytes present only in the new content.
+    Insertion,
+    /// Bytes of the old content replaced with bytes of the new content.
+    Replacement,
+}
+
+/// A block of bytes changed between contents of two versions.
+///
+/// Returned by [`Sbvc::diff_between`].
+///
+/// [`Sbvc::diff_between`]: Sbvc::diff_between
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockEdit {
+    /// Bytes in the specified range of the old content were deleted.
+    Deletion(Range<usize>),
+    /// Bytes in the specified range of the new content were inserted.
+    Insertion(Range<usize>),
+    /// Bytes in `from_range` of the old content were moved to `to_range` of
+    /// the new content.
+    Move {
+        /// The range of the block in the old content.
+        from_range: Range<usize>,
+        /// The range of the block in the new content.
+        to_range: Range<usize>,
+    },
+}
+
+/// The result of comparing two version trees.
+///
+/// Returned by [`Sbvc::tree_diff`].
+///
+/// [`Sbvc::tree_diff`]: Sbvc::tree_diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeDiff {
+    only_self: Vec<u32>,
+    only_other: Vec<u32>,
+    common: Vec<(u32, u32)>,
+}
+
+impl TreeDiff {
+    /// Returns IDs of versions whose contents are present only in the tree
+    /// that was compared.
+    pub fn only_self(&self) -> &[u32] {
+        &self.only_self
+    }
+
+    /// Returns IDs of versions whose contents are present only in the tree
+    /// that was compared against.
+    pub fn only_other(&self) -> &[u32] {
+        &self.only_other
+    }
+
+    /// Returns pairs of IDs of versions with the same contents present in both
+    /// trees. The left value in the tuple corresponds to the tree that was
+    /// compared, and the right value to the tree that was compared against.
+    pub fn common(&self) -> &[(u32, u32)] {
+        &self.common
+    }
+}
+
+/// A merge of a version into the current version in progress.
+///
+/// Returned by [`Sbvc::merge`].
+///
+/// [`Sbvc::merge`]: Sbvc::merge
+#[derive(Debug)]
+pub struct MergeSession<'a> {
+    sbvc: &'a mut Sbvc,
+    id: u32,
+    // Merged contents are the clean parts interleaved with the conflicts
+    clean: Vec<Vec<u8>>,
+    conflicts: Vec<Conflict>,
+    finalized: bool,
+}
+
+impl MergeSession<'_> {
+    /// Returns the conflicts of the merge, in the order they appear in the
+    /// contents.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Returns `true` if every conflict is resolved.
+    pub fn is_resolved(&self) -> bool {
+        self.conflicts.iter().all(Conflict::is_resolved)
+    }
+
+    /// Resolves the conflict with the specified range of the contents of the
+    /// common ancestor, replacing the previous resolution if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SbvcError::InvalidOperation`] if there is no conflict with
+    /// such range.
+    ///
+    /// [`SbvcError::InvalidOperation`]: SbvcError::InvalidOperation
+    pub fn resolve(
+        &mut self,
+        range: Range<usize>,
+        resolution: Resolution,
+    ) -> SbvcResult<()> {
+        let conflict = self
+            .conflicts
+            .iter_mut()
+            .find(|conflict| conflict.base_range == range)
+            .ok_or_else(|| {
+                SbvcError::InvalidOperation(format!(
+                    "No conflict with range {:?}",
+                    range
+                ))
+            })?;
+
+        conflict.resolution = Some(match resolution {
+            Resolution::Ours => conflict.ours.clone(),
+            Resolution::Theirs => conflict.theirs.clone(),
+            Resolution::Custom(content) => content,
+        });
+        Ok(())
+    }
+
+    /// Commits the merged contents as a new version branching from the current
+    /// one, with the merged version as its additional parent, and checks it
+    /// out. The new version is named `merge <id>`. Like [`Sbvc::commit_from`],
+    /// this method does not change the tracked file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an IO error occurs, if the merge was already
+    /// finalized, or [`SbvcError::MergeConflict`] with the first conflict that
+    /// is not resolved.
+    ///
+    /// [`Sbvc::commit_from`]: Sbvc::commit_from
+    /// [`SbvcError::MergeConflict`]: SbvcError::MergeConflict
+    pub fn finalize(&mut self) -> SbvcResult<u32> {
+        if self.finalized {
+            return Err(SbvcError::InvalidOperation(
+                "Merge is already finalized".to_string(),
+            ));
+        }
+
+        if let Some(conflict) =
+            self.conflicts.iter().find(|conflict| !conflict.is_resolved())
+        {
+            return Err(SbvcError::MergeConflict(conflict.base_range()));
+        }
+
+        let mut content = self.clean[0].clone();
+
+        for (conflict, clean) in self.conflicts.iter().zip(&self.clean[1..]) {
+            content.extend_from_slice(conflict.resolution.as_ref().unwrap());
+            content.extend_from_slice(clean);
+        }
+
+        let sbvc = &mut *self.sbvc;
+        sbvc.check_writable()?;
+        sbvc.pre_commit_hook.check(&content)?;
+        let id = sbvc.add_version(
+            sbvc.current,
+            content,
+            None,
+            &format!("merge {}", self.id),
+            "",
+            SystemTime::now(),
+        );
+        sbvc.versions[sbvc.current].extra_parents.push(self.id);
+        self.finalized = true;
+        sbvc.write()?;
+        Ok(id)
+    }
+}
+
+/// Changes to the same part of contents made by both sides of a merge.
+///
+/// Returned by [`MergeSession::conflicts`].
+///
+/// [`MergeSession::conflicts`]: MergeSession::conflicts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    base_range: Range<usize>,
+    ours: Vec<u8>,
+    theirs: Vec<u8>,
+    resolution: Option<Vec<u8>>,
+}
+
+impl Conflict {
+    /// Returns the range of the contents of the common ancestor both sides
+    /// changed.
+    pub fn base_range(&self) -> Range<usize> {
+        self.base_range.clone()
+    }
+
+    /// Returns what the current version replaced the range with.
+    pub fn ours(&self) -> &[u8] {
+        &self.ours
+    }
+
+    /// Returns what the merged version replaced the range with.
+    pub fn theirs(&self) -> &[u8] {
+        &self.theirs
+    }
+
+    /// Returns `true` if the conflict is resolved.
+    pub fn is_resolved(&self) -> bool {
+        self.resolution.is_some()
+    }
+}
+
+/// How to resolve a [`Conflict`].
+///
+/// [`Conflict`]: Conflict
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Take the changes of the current version.
+    Ours,
+    /// Take the changes of the merged version.
+    Theirs,
+    /// Replace the range with the specified bytes.
+    Custom(Vec<u8>),
+}
+
+/// An immutable representation of a version
+#[derive(Debug, Clone)]
+pub struct Version {
+    id: u32,
+    base: u32,
+    name: String,
+    description: String,
+    date: SystemTime,
+    difference: OwnedDifference<u8>,
+    extra_parents: Vec<u32>,
+    source_path: Option<PathBuf>,
+    moved_from: Option<PathBuf>,
+    snapshot: bool,
+    signature: Option<Vec<u8>>,
+    parent_hash: Option<String>,
+    checksum: Option<String>,
+    lazy: Option<LazyInsertions>,
+}
+
+#[derive(Clone, Copy)]
+enum Payload<'a> {
+    Copy,
+    Skip,
+    Lazy(&'a Arc<[u8]>),
+    Inflate,
+    // Insertions are ranges of the version tree file, read right away unless
+    // the first flag is set, and decompressed if the second one is set
+    Indexed(&'a SharedReader, bool, bool),
+}
+
+trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+type SharedReader = Arc<Mutex<Box<dyn ReadSeek>>>;
+
+fn read_range(
+    reader: &SharedReader,
+    range: Range<usize>,
+) -> io::Result<Vec<u8>> {
+    let mut reader = reader.lock().unwrap_or_else(|error| error.into_inner());
+    let mut data = vec![0; range.len()];
+    reader.seek(SeekFrom::Start(range.start as u64))?;
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+type HookFn = dyn Fn(&[u8]) -> Result<(), String> + Send + Sync;
+
+// Function that can veto commits
+#[derive(Clone, Default)]
+struct Hook(Option<Arc<HookFn>>);
+
+impl Hook {
+    fn check(&self, content: &[u8]) -> SbvcResult<()> {
+        match &self.0 {
+            Some(hook) => hook(content).map_err(SbvcError::HookRejected),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Debug for Hook {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Hook").finish_non_exhaustive()
+    }
+}
+
+// Versions removed by the last deletion, so that it can be undone
+#[derive(Debug, Clone, Default)]
+struct Deleted {
+    versions: Vec<Version>,
+    // Pairs of IDs of remaining versions and their removed extra parents
+    extra_parents: Vec<(u32, u32)>,
+}
+
+#[derive(Clone)]
+enum LazySource {
+    Memory(Arc<[u8]>),
+    // The version tree file stays open, and since it is replaced rather than
+    // changed in place when saved, keeps the contents it was indexed with
+    File(SharedReader),
+}
+
+impl LazySource {
+    fn read(&self, range: Range<usize>) -> Vec<u8> {
+        match self {
+            LazySource::Memory(source) => source[range].to_vec(),
+            LazySource::File(reader) => read_range(reader, range)
+                .expect("Failed to read inserted bytes from version tree file"),
+        }
+    }
+}
+
+impl Debug for LazySource {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LazySource::Memory(source) => {
+                f.debug_tuple("Memory").field(&source.len()).finish()
+            }
+            LazySource::File(_) => {
+                f.debug_tuple("File").finish_non_exhaustive()
+            }
+        }
+    }
+}
+
+// Contents of recently reconstructed versions
+#[derive(Debug, Default)]
+struct Cache(Mutex<Vec<(u32, Vec<u8>)>>);
+
+impl Cache {
+    fn entries(&self) -> MutexGuard<'_, Vec<(u32, Vec<u8>)>> {
+        self.0.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    fn get(&self, id: u32) -> Option<Vec<u8>> {
+        self.entries()
+            .iter()
+            .find(|&&(cached, _)| cached == id)
+            .map(|(_, content)| content.clone())
+    }
+
+    fn insert(&self, id: u32, content: &[u8]) {
+        let mut entries = self.entries();
+        entries.retain(|&(cached, _)| cached != id);
+
+        if entries.len() == CACHE_SIZE {
+            entries.remove(0);
+        }
+
+        entries.push((id, content.to_vec()));
+    }
+}
+
+impl Clone for Cache {
+    fn clone(&self) -> Self {
+        Cache(Mutex::new(self.entries().clone()))
+    }
+}
+
+// Inserted bytes of a version that stay in the version tree file contents
+// until they are needed
+#[derive(Debug, Clone)]
+struct LazyInsertions {
+    source: LazySource,
+    ranges: Vec<Range<usize>>,
+    difference: OnceLock<OwnedDifference<u8>>,
+}
+
+impl Version {
+    fn from_nelf(source: &[u8]) -> SbvcResult<Version> {
+        Version::from_nelf_private(source, Payload::Copy, None)
     }
 
-    /// Returns the path to the version tree file.
-    pub fn path(&self) -> &Path {
-        &self.path
+    // If `blobs` is specified, insertions refer to their contents by keys
+    fn from_nelf_private(
+        source: &[u8],
+        payload: Payload,
+        blobs: Option<&Blobs>,
+    ) -> SbvcResult<Version> {
+        let mut iter = NelfIter::from_string(source);
+
+        let id: u32 = str::from_utf8(iter.next().ok_or_else(|| {
+            SbvcError::InvalidFormat("Expected version id".to_string())
+        })?)?
+        .parse()?;
+
+        let base = str::from_utf8(iter.next().ok_or_else(|| {
+            SbvcError::InvalidFormat("Expected base version id".to_string())
+        })?)?
+        .parse()?;
+
+        let mut meta = NelfIter::from_string(iter.next().ok_or_else(|| {
+            SbvcError::InvalidFormat("Expected version metadata".to_string())
+        })?);
+
+        let name = utf8_field(
+            meta.next().ok_or_else(|| {
+                SbvcError::InvalidFormat("Expected version name".to_string())
+            })?,
+            || format!("name of version {}", id),
+        )?
+        .to_string();
+
+        let date = SystemTime::UNIX_EPOCH
+            + Duration::from_secs(
+                str::from_utf8(meta.next().ok_or_else(|| {
+                    SbvcError::InvalidFormat(
+                        "Expected version creation date".to_string(),
+                    )
+                })?)?
+                .parse()?,
+            );
+
+        let source_path = match meta.next() {
+            Some(source_path) if !source_path.is_empty() => {
+                Some(path_from_bytes(source_path, || {
+                    format!("source path of version {}", id)
+                })?)
+            }
+            _ => None,
+        };
+
+        let moved_from = match meta.next() {
+            Some(moved_from) if !moved_from.is_empty() => {
+                Some(path_from_bytes(moved_from, || {
+                    format!("previous path of version {}", id)
+                })?)
+            }
+            _ => None,
+        };
+
+        let signature = match meta.next() {
+            Some(signature) if !signature.is_empty() => {
+                Some(parse_hex(signature).ok_or_else(|| {
+                    SbvcError::InvalidFormat(format!(
+                        "Invalid signature of version {}",
+                        id
+                    ))
+                })?)
+            }
+            _ => None,
+        };
+
+        let parent_hash = match meta.next() {
+            Some(parent_hash) if !parent_hash.is_empty() => Some(
+                utf8_field(parent_hash, || {
+                    format!("parent hash of version {}", id)
+                })?
+                .to_string(),
+            ),
+            _ => None,
+        };
+
+        let description = match meta.next() {
+            Some(description) => utf8_field(description, || {
+                format!("description of version {}", id)
+            })?
+            .to_string(),
+            None => String::new(),
+        };
+
+        let checksum = match meta.next() {
+            Some(checksum) if !checksum.is_empty() => Some(
+                utf8_field(checksum, || format!("checksum of version {}", id))?
+                    .to_string(),
+            ),
+            _ => None,
+        };
+
+        let mut difference = OwnedDifference::empty();
+        let mut ranges = Vec::new();
+
+        for deletion in NelfIter::from_string(iter.next().ok_or_else(|| {
+            SbvcError::InvalidFormat("Expected version deletions".to_string())
+        })?)
+        .map(|source| -> SbvcResult<Deletion> {
+            let mut iter = NelfIter::from_string(source);
+
+            let start: usize =
+                str::from_utf8(iter.next().ok_or_else(|| {
+                    SbvcError::InvalidFormat(
+                        "Expected deletion start".to_string(),
+                    )
+                })?)?
+                .parse()?;
+
+            let end: usize = str::from_utf8(iter.next().ok_or_else(|| {
+                SbvcError::InvalidFormat("Expected deletion end".to_string())
+            })?)?
+            .parse()?;
+
+            Ok(Deletion { start, end })
+        }) {
+            difference.deletions.push(deletion?);
+        }
+
+        for insertion in
+            NelfIter::from_string(iter.next().ok_or_else(|| {
+                SbvcError::InvalidFormat(
+                    "Expected version insertions".to_string(),
+                )
+            })?)
+            .map(|source| -> SbvcResult<OwnedInsertion<u8>> {
+                let mut iter = NelfIter::from_string(source);
+
+                let start: usize =
+                    str::from_utf8(iter.next().ok_or_else(|| {
+                        SbvcError::InvalidFormat(
+                            "Expected insertion start".to_string(),
+                        )
+                    })?)?
+                    .parse()?;
+
+                let mut source = iter.next().ok_or_else(|| {
+                    SbvcError::InvalidFormat(
+                        "Expected insertion data".to_string(),
+                    )
+                })?;
+
+                if let Some(blobs) = blobs {
+                    source = blobs.get(source).ok_or_else(|| {
+                        SbvcError::InvalidFormat(format!(
+                            "Blob {} of version {} not found",
+                            String::from_utf8_lossy(source),
+                            id
+                        ))
+                    })?;
+                }
+
+                let data = match payload {
+                    Payload::Copy => source.to_vec(),
+                    Payload::Skip => Vec::new(),
+                    Payload::Inflate => inflate(source)?,
+                    Payload::Lazy(whole) => {
+                        let start =
+                            source.as_ptr() as usize - whole.as_ptr() as usize;
+                        ranges.push(start..start + source.len());
+                        Vec::new()
+                    }
+                    Payload::Indexed(reader, lazy, compressed) => {
+                        let range = parse_range(source).ok_or_else(|| {
+                            SbvcError::InvalidFormat(format!(
+                                "Invalid range of inserted bytes of version {}",
+                                id
+                            ))
+                        })?;
+
+                        if lazy {
+                            ranges.push(range);
+                            Vec::new()
+                        } else if compressed {
+                            inflate(&read_range(reader, range)?)?
+                        } else {
+                            read_range(reader, range)?
+                        }
+                    }
+                };
+
+                Ok(OwnedInsertion { start, data })
+            })
+        {
+            difference.insertions.push(insertion?);
+        }
+
+        let mut extra_parents = Vec::new();
+
+        if let Some(source) = iter.next() {
+            for parent in NelfIter::from_string(source) {
+                extra_parents.push(str::from_utf8(parent)?.parse()?);
+            }
+        }
+
+        let snapshot = iter.next() == Some(b"1");
+
+        Ok(Version {
+            id,
+            base,
+            name,
+            description,
+            date,
+            difference,
+            extra_parents,
+            source_path,
+            moved_from,
+            snapshot,
+            signature,
+            parent_hash,
+            checksum,
+            lazy: match payload {
+                Payload::Lazy(whole) => Some(LazyInsertions {
+                    source: LazySource::Memory(whole.clone()),
+                    ranges,
+                    difference: OnceLock::new(),
+                }),
+                Payload::Indexed(reader, true, _) => Some(LazyInsertions {
+                    source: LazySource::File(reader.clone()),
+                    ranges,
+                    difference: OnceLock::new(),
+                }),
+                _ => None,
+            },
+        })
     }
 
-    /// Returns the path to the tracked file.
-    pub fn file(&self) -> &Path {
-        &self.file
+    fn init() -> Self {
+        Version {
+            id: 0,
+            base: 0,
+            name: INIT_VERSION_NAME.to_string(),
+            description: String::new(),
+            date: SystemTime::now(),
+            difference: OwnedDifference::empty(),
+            extra_parents: Vec::new(),
+            source_path: None,
+            moved_from: None,
+            snapshot: false,
+            signature: None,
+            parent_hash: None,
+            checksum: None,
+            lazy: None,
+        }
     }
 
-    /// Sets the tracked file for this version tree.
-    ///
-    /// # Errors
-    ///
-    /// Fails if an IO error occurs.
-    pub fn set_file(&mut self, file: PathBuf) -> SbvcResult<()> {
-        self.file = file;
-        self.write()
+    fn loaded(&self) -> &OwnedDifference<u8> {
+        match &self.lazy {
+            Some(lazy) => lazy.difference.get_or_init(|| OwnedDifference {
+                deletions: self.difference.deletions.clone(),
+                insertions: self
+                    .difference
+                    .insertions
+                    .iter()
+                    .zip(&lazy.ranges)
+                    .map(|(insertion, range)| {
+                        OwnedInsertion::new(
+                            insertion.start,
+                            lazy.source.read(range.clone()),
+                        )
+                    })
+                    .collect(),
+            }),
+            None => &self.difference,
+        }
     }
 
-    /// Returns a reference to the current version (For info).
-    pub fn current(&self) -> &Version {
-        &self.versions[self.current]
+    fn set_difference(&mut self, base: &[u8], content: &[u8]) {
+        (self.difference, self.snapshot) = difference(content, base);
+        self.parent_hash = Some(hash(base));
+        self.checksum = Some(hash(content));
+        self.lazy = None;
     }
 
-    fn version(&self, id: u32) -> Option<usize> {
-        self.versions
-            .iter()
-            .enumerate()
-            .find(|&(_, version)| version.id == id)
-            .map(|(index, _)| index)
+    fn apply(&self, content: &mut Vec<u8>) {
+        let mut difference = self.difference();
+
+        // Contents of snapshots do not depend on the base, which may be
+        // missing
+        if self.snapshot {
+            content.clear();
+            difference.deletions.clear();
+        }
+
+        content.patch(difference);
     }
 
-    /// Returns a slice of all versions.
-    pub fn versions(&self) -> &[Version] {
-        &self.versions
+    fn to_nelf(&self) -> Vec<u8> {
+        self.to_nelf_private(false, None)
     }
-}
 
-trait ToNewlineNelf {
-    fn to_newline_nelf(self) -> Vec<u8>;
-}
+    // If `keys` are specified, they are written instead of insertion contents
+    fn to_nelf_private(
+        &self,
+        compress: bool,
+        keys: Option<&[String]>,
+    ) -> Vec<u8> {
+        let source_path =
+            self.source_path.as_deref().map(path_to_bytes).unwrap_or_default();
+        let moved_from =
+            self.moved_from.as_deref().map(path_to_bytes).unwrap_or_default();
 
-impl<T: IntoIterator<Item = V>, V: ToCell> ToNewlineNelf for T {
-    fn to_newline_nelf(self) -> Vec<u8> {
-        self.into_iter()
-            .flat_map(|string| {
-                let mut cell = string.to_cell();
-                cell.push(b'\n');
-                cell
-            })
-            .collect()
-    }
-}
+        // Dates before the Unix epoch, e.g. from a clock set far in the past,
+        // cannot be stored and are written as the epoch itself
+        let date = self
+            .date
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs();
 
-/// An immutable representation of a version
-#[derive(Debug, Clone)]
-pub struct Version {
-    id: u32,
-    base: u32,
-    name: String,
-    date: SystemTime,
-    difference: OwnedDifference<u8>,
-}
+        [
+            self.id.to_string().as_bytes(),
+            self.base.to_string().as_bytes(),
+            &[
+                self.name.as_bytes(),
+                date.to_string().as_bytes(),
+                &source_path,
+                &moved_from,
+                self.signature
+                    .as_ref()
+                    .map_or(String::new(), |signature| {
+                        signature
+                            .iter()
+                            .map(|byte| format!("{:02x}", byte))
+                            .collect()
+                    })
+                    .as_bytes(),
+                self.parent_hash.as_deref().unwrap_or_default().as_bytes(),
+                self.description.as_bytes(),
+                self.checksum.as_deref().unwrap_or_default().as_bytes(),
+            ]
+            .to_newline_nelf(),
+            &self
+                .loaded()
+                .deletions
+                .iter()
+                .map(|deletion| {
+                    [
+                        deletion.start.to_string().as_bytes(),
+                        deletion.end.to_string().as_bytes(),
+                    ]
+                    .to_newline_nelf()
+                })
+                .to_newline_nelf(),
+            &self
+                .loaded()
+                .insertions
+                .iter()
+                .enumerate()
+                .map(|(index, insertion)| {
+                    let data = match keys {
+                        Some(keys) => Cow::Borrowed(keys[index].as_bytes()),
+                        None if compress => {
+                            Cow::Owned(deflate(&insertion.data))
+                        }
+                        None => Cow::Borrowed(&insertion.data[..]),
+                    };
+
+                    [insertion.start.to_string().as_bytes(), &data]
+                        .to_newline_nelf()
+                })
+                .to_newline_nelf(),
+            &self
+                .extra_parents
+                .iter()
+                .map(|parent| parent.to_string().into_bytes())
+                .to_newline_nelf(),
+            if self.snapshot { b"1" } else { b"0" },
+        ]
+        .to_newline_nelf()
+    }
 
-impl Version {
     /// Returns the version ID.
     pub fn id(&self) -> u32 {
         self.id
@@ -530,17 +5562,386 @@ impl Version {
     }
 
     /// Returns the version name.
+    ///
+    /// Names, like descriptions, may contain any characters, including
+    /// newlines, since cells of the version tree file need no escaping.
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    /// Returns the version description, which is empty unless it was set.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Returns IDs of additional parents of the version, e.g. the second
+    /// parent of a merge.
+    pub fn extra_parents(&self) -> &[u32] {
+        &self.extra_parents
+    }
+
+    /// Returns the path the tracked file had when the version was committed,
+    /// if it was committed from the tracked file.
+    pub fn source_path(&self) -> Option<&Path> {
+        self.source_path.as_deref()
+    }
+
+    /// Returns `true` if the version stores a checksum of its contents, which
+    /// [`Sbvc::verify`] compares them against. Versions committed with older
+    /// versions of the library have none.
+    pub fn has_checksum(&self) -> bool {
+        self.checksum.is_some()
+    }
+
+    /// Returns `true` if the version stores a signature made with
+    /// [`Sbvc::set_signing_key`].
+    pub fn is_signed(&self) -> bool {
+        self.signature.is_some()
+    }
+
+    /// Returns the previous path of the tracked file, if the version records
+    /// a relocation of the tracked file made with [`Sbvc::relocate`].
+    pub fn moved_from(&self) -> Option<&Path> {
+        self.moved_from.as_deref()
+    }
+
     /// Returns the version creation time.
     pub fn date(&self) -> SystemTime {
         self.date
     }
 
+    /// Returns the time elapsed since the version creation.
+    ///
+    /// Returns zero if the version was created in the future (e.g. because of
+    /// clock skew).
+    pub fn relative_age(&self) -> Duration {
+        SystemTime::now().duration_since(self.date).unwrap_or(Duration::ZERO)
+    }
+
+    /// Returns the size of the difference of this version from the base
+    /// version, that is, the number of inserted bytes plus the number of
+    /// deleted bytes.
+    pub fn stored_size(&self) -> usize {
+        self.inserted_size() + self.deleted_size()
+    }
+
+    fn inserted_size(&self) -> usize {
+        match &self.lazy {
+            Some(lazy) => lazy.ranges.iter().map(|range| range.len()).sum(),
+            None => self
+                .difference
+                .insertions
+                .iter()
+                .map(|insertion| insertion.data.len())
+                .sum(),
+        }
+    }
+
+    fn deleted_size(&self) -> usize {
+        self.difference.deletions.iter().map(|deletion| deletion.len()).sum()
+    }
+
+    // Returns length of contents of the version given length of contents of
+    // its base, or the first offset of the difference that is out of bounds.
+    // Unlike `applies_to`, does not load insertions
+    fn patched_len(&self, mut len: usize) -> Result<usize, usize> {
+        let mut end = 0;
+
+        for deletion in &self.difference.deletions {
+            if deletion.start < end || deletion.end < deletion.start {
+                return Err(deletion.start);
+            }
+
+            if deletion.end > len {
+                return Err(deletion.end);
+            }
+
+            end = deletion.end;
+        }
+
+        len -= self.deleted_size();
+
+        let lens: Vec<_> = match &self.lazy {
+            Some(lazy) => lazy.ranges.iter().map(|range| range.len()).collect(),
+            None => self
+                .difference
+                .insertions
+                .iter()
+                .map(|insertion| insertion.data.len())
+                .collect(),
+        };
+
+        for (insertion, data_len) in self.difference.insertions.iter().zip(lens)
+        {
+            if insertion.start > len {
+                return Err(insertion.start);
+            }
+
+            len += data_len;
+        }
+
+        Ok(len)
+    }
+
+    /// Returns `false` if the version tree was opened with [`Sbvc::open_lazy`]
+    /// and inserted bytes of this version have not been loaded yet.
+    pub fn is_loaded(&self) -> bool {
+        self.lazy.as_ref().is_none_or(|lazy| lazy.difference.get().is_some())
+    }
+
+    /// Returns `true` if the version is stored as a snapshot of its contents,
+    /// so reconstructing them does not require contents of the base version.
+    ///
+    /// Versions are stored as snapshots when the difference from the base
+    /// version would be larger than the contents themselves.
+    pub fn is_snapshot(&self) -> bool {
+        self.snapshot
+    }
+
     /// Returns the difference of this version from the base version.
-    pub fn difference(&self) -> Difference<u8> {
-        self.difference.borrow()
+    ///
+    /// If the version is a snapshot, the difference deletes all contents of the
+    /// base version and inserts the contents of this version.
+    pub fn difference(&self) -> Difference<'_, u8> {
+        self.loaded().borrow()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pre_epoch_date() -> SbvcResult<()> {
+        const PATH: &str = "pre_epoch_date.nelf";
+        const FILE: &str = "pre_epoch_date";
+
+        let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+        sbvc.versions[0].date = SystemTime::UNIX_EPOCH - Duration::from_secs(1);
+        sbvc.rename("renamed")?;
+
+        let sbvc = Sbvc::open(PATH.into())?;
+        assert_eq!(sbvc.current().date(), SystemTime::UNIX_EPOCH);
+
+        fs::remove_file(PATH)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_reconstruction_mismatch() -> SbvcResult<()> {
+        const PATH: &str = "verify_reconstruction_mismatch.nelf";
+        const FILE: &str = "verify_reconstruction_mismatch";
+
+        let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+        fs::write(FILE, b"0123456789")?;
+        sbvc.commit()?;
+        fs::write(FILE, b"01234")?;
+        sbvc.commit()?;
+
+        // A version wrongly marked as a snapshot still applies to its base,
+        // so only the cross-check notices
+        let mut sbvc = Sbvc::open(PATH.into())?;
+        sbvc.versions[2].snapshot = true;
+        sbvc.verify_reconstruction(1)?;
+        assert!(matches!(
+            sbvc.verify_reconstruction(2),
+            Err(SbvcError::InvalidFormat(error))
+                if error.contains("ID 2") && error.contains("offset 0")
+        ));
+
+        fs::remove_file(PATH)?;
+        fs::remove_file(FILE)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_current_out_of_bounds() -> SbvcResult<()> {
+        const PATH: &str = "try_current_out_of_bounds.nelf";
+        const FILE: &str = "try_current_out_of_bounds";
+
+        let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+        assert_eq!(sbvc.try_current()?.id(), 0);
+
+        sbvc.current = sbvc.versions.len();
+        assert!(matches!(
+            sbvc.try_current(),
+            Err(SbvcError::InvalidOperation(error))
+                if error.contains("out of bounds of 1 versions")
+        ));
+
+        fs::remove_file(PATH)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_indexed_skips_insertions() -> SbvcResult<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const PATH: &str = "open_indexed_skips_insertions.nelf";
+        const FILE: &str = "open_indexed_skips_insertions";
+
+        struct Counting(fs::File, Arc<AtomicUsize>);
+
+        impl Read for Counting {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let len = self.0.read(buf)?;
+                self.1.fetch_add(len, Ordering::Relaxed);
+                Ok(len)
+            }
+        }
+
+        impl Seek for Counting {
+            fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+
+        let data = vec![b'A'; 4096];
+        let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+        sbvc.set_indexed(true)?;
+        fs::write(FILE, &data)?;
+        sbvc.commit_with_name("first")?;
+
+        let modified = fs::metadata(PATH)?.modified()?;
+        let len = fs::metadata(PATH)?.len();
+        let index = fs::read(with_suffix(Path::new(PATH), INDEX_EXTENSION))?;
+        let read = Arc::new(AtomicUsize::new(0));
+        let sbvc = Sbvc::open_indexed(
+            Counting(fs::File::open(PATH)?, read.clone()),
+            PATH.into(),
+            parse_index(&index, len, modified).unwrap(),
+            None,
+            true,
+            &Limits::default(),
+        )?;
+
+        assert_eq!(sbvc.current().name(), "first");
+        assert!(read.load(Ordering::Relaxed) < 100);
+        assert_eq!(sbvc.content(1)?, data);
+        assert!(read.load(Ordering::Relaxed) > data.len());
+        assert!(parse_index(&index, len + 1, modified).is_none());
+
+        fs::remove_file(PATH)?;
+        fs::remove_file(with_suffix(Path::new(PATH), INDEX_EXTENSION))?;
+        fs::remove_file(FILE)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn durable_write() -> SbvcResult<()> {
+        const PATH: &str = "durable_write.nelf";
+        const FILE: &str = "durable_write";
+
+        let syncs = || SYNCS.with(|syncs| syncs.get());
+        let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+        let count = syncs();
+        sbvc.rename("fast")?;
+        assert_eq!(syncs(), count);
+
+        sbvc.set_durable(true);
+        sbvc.rename("durable")?;
+        // The file itself, and its directory where it can be synced
+        assert_eq!(syncs(), count + if cfg!(unix) { 2 } else { 1 });
+
+        let temporary = with_suffix(Path::new(PATH), TEMPORARY_EXTENSION);
+        assert!(fs::metadata(temporary).is_err());
+        assert_eq!(Sbvc::open(PATH.into())?.current().name(), "durable");
+
+        fs::remove_file(PATH)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn first_version_with_content_checksums() -> SbvcResult<()> {
+        const PATH: &str = "first_version_with_content_checksums.nelf";
+        const FILE: &str = "first_version_with_content_checksums";
+
+        let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+        fs::write(FILE, b"FIRST")?;
+        sbvc.commit()?;
+        fs::write(FILE, b"SECOND")?;
+        sbvc.commit()?;
+        fs::write(FILE, b"FIRST")?;
+        sbvc.commit()?;
+
+        // Versions are skipped by their checksums, and a matching checksum
+        // alone is not enough
+        sbvc.versions[1].checksum = Some(hash(b"OTHER"));
+        sbvc.versions[2].checksum = Some(hash(b"FIRST"));
+        assert_eq!(sbvc.first_version_with_content(b"FIRST"), Some(3));
+        assert_eq!(sbvc.first_version_with_content(b"SECOND"), None);
+
+        fs::remove_file(PATH)?;
+        fs::remove_file(FILE)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_between_borrows_cache() -> SbvcResult<()> {
+        const PATH: &str = "diff_between_borrows_cache.nelf";
+        const FILE: &str = "diff_between_borrows_cache";
+
+        let calls = || DATA_CALLS.with(|calls| calls.get());
+        let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+        fs::write(FILE, b"FIRST")?;
+        sbvc.commit()?;
+        fs::write(FILE, b"SECOND")?;
+        sbvc.commit()?;
+
+        let sbvc = Sbvc::open(PATH.into())?;
+        let count = calls();
+        let edits = sbvc.diff_between(1, 2, false)?;
+        assert_eq!(calls(), count + 2);
+
+        // Both versions are cached now
+        assert_eq!(sbvc.diff_between(1, 2, false)?, edits);
+        sbvc.diff_between(2, 1, true)?;
+        assert_eq!(calls(), count + 2);
+        assert!(matches!(
+            sbvc.diff_between(1, 3, false),
+            Err(SbvcError::VersionNotFound(3))
+        ));
+
+        fs::remove_file(PATH)?;
+        fs::remove_file(FILE)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn cell_bytes_match_nelf_iter() -> io::Result<()> {
+        let sources: [&[u8]; 9] = [
+            b"C|A|C",
+            b"C||A||C||B||C",
+            b"C//A\\\\C\\\\B//C",
+            b"||A|A||",
+            b"/A\\\\/\\",
+            b"/\\",
+            b"|ABC",
+            b"//A\\",
+            b"123",
+        ];
+
+        for source in sources {
+            let mut bytes = source.iter().copied().map(Ok);
+            let mut cells = Vec::new();
+
+            while let Some(cell) = next_cell(&mut bytes)? {
+                cells.push(cell.collect::<io::Result<Vec<_>>>()?);
+            }
+
+            assert_eq!(
+                cells,
+                NelfIter::from_string(source).collect::<Vec<_>>()
+            );
+        }
+
+        Ok(())
     }
 }
@@ -1,6 +1,9 @@
-use std::{fs, str};
+use std::{fs, io::Cursor, path::Path, str, time::Duration};
 
-use sbvc_lib::{Sbvc, SbvcResult};
+use sbvc_lib::{
+    BlockEdit, DiffLine, EditKind, Limits, Resolution, Sbvc, SbvcError,
+    SbvcResult, Version, Warning,
+};
 
 #[test]
 fn create() -> SbvcResult<()> {
@@ -86,3 +89,3393 @@ fn rename() -> SbvcResult<()> {
 
     Ok(())
 }
+
+#[cfg(unix)]
+#[test]
+fn commit_readonly() -> SbvcResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    const DIR: &str = "commit_readonly";
+    const PATH: &str = "commit_readonly/commit_readonly.nelf";
+    const FILE: &str = "commit_readonly.txt";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let _ = fs::remove_dir_all(DIR);
+    fs::create_dir(DIR)?;
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA)?;
+    fs::set_permissions(DIR, fs::Permissions::from_mode(0o555))?;
+
+    let result = sbvc.commit();
+
+    fs::set_permissions(DIR, fs::Permissions::from_mode(0o755))?;
+    assert!(result.is_err());
+    assert_eq!(sbvc.versions().len(), 1);
+    assert_eq!(sbvc.current().id(), 0);
+
+    fs::remove_dir_all(DIR)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn name_is_unique() -> SbvcResult<()> {
+    const PATH: &str = "name_is_unique.nelf";
+    const FILE: &str = "name_is_unique";
+    const NAME: &str = "duplicated";
+
+    fs::write(FILE, b"")?;
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.commit()?;
+    sbvc.rename(NAME)?;
+    sbvc.commit()?;
+    sbvc.rename(NAME)?;
+
+    assert!(!sbvc.name_is_unique(NAME, None));
+    assert!(!sbvc.name_is_unique(NAME, Some(2)));
+    assert!(sbvc.name_is_unique("unique", None));
+
+    sbvc.checkout(1, false)?;
+    sbvc.rename("unique")?;
+    assert!(sbvc.name_is_unique(NAME, Some(2)));
+    assert!(sbvc.name_is_unique("unique", Some(1)));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn open_lenient() -> SbvcResult<()> {
+    const PATH: &str = "open_lenient.nelf";
+    const FILE: &str = "open_lenient";
+
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.commit()?;
+
+    let source = fs::read(PATH)?;
+    let mut lines = source.splitn(3, |&ch| ch == b'\n');
+    let mut corrupted = Vec::new();
+    corrupted.extend_from_slice(lines.next().unwrap());
+    corrupted.extend_from_slice(b"\n|7|\n");
+    corrupted.extend_from_slice(lines.nth(1).unwrap());
+    fs::write(PATH, corrupted)?;
+
+    assert!(Sbvc::open(PATH.into()).is_err());
+
+    let (sbvc, warnings) = Sbvc::open_lenient(PATH.into())?;
+    assert_eq!(sbvc.current().id(), 0);
+    assert_eq!(sbvc.versions().len(), 2);
+    assert_eq!(warnings, [Warning::CurrentReset(7)]);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn checkout_default() -> SbvcResult<()> {
+    const PATH: &str = "checkout_default.nelf";
+    const FILE: &str = "checkout_default";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+
+    assert!(!sbvc.default_rollback());
+    sbvc.checkout_default(1)?;
+    assert_eq!(sbvc.current().id(), 1);
+    assert_eq!(fs::read(FILE)?, DATA_2);
+
+    sbvc.set_default_rollback(true);
+    sbvc.checkout_default(2)?;
+    sbvc.checkout_default(1)?;
+    assert_eq!(fs::read(FILE)?, DATA_1);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn relative_age() -> SbvcResult<()> {
+    const PATH: &str = "relative_age.nelf";
+    const FILE: &str = "relative_age";
+
+    let sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    assert!(sbvc.current().relative_age() < Duration::from_secs(5));
+
+    fs::remove_file(PATH)?;
+
+    Ok(())
+}
+
+#[test]
+fn extra_parents() -> SbvcResult<()> {
+    const PATH: &str = "extra_parents.nelf";
+    const FILE: &str = "extra_parents";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+    sbvc.checkout(0, false)?;
+    sbvc.commit()?;
+    sbvc.set_extra_parents(3, vec![2])?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.current().extra_parents(), [2]);
+    assert_eq!(sbvc.ancestors(3, false)?, [0]);
+    assert_eq!(sbvc.ancestors(3, true)?, [0, 2, 1]);
+    assert!(!sbvc.is_ancestor(1, 3, false)?);
+    assert!(sbvc.is_ancestor(1, 3, true)?);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn export_patch_series() -> SbvcResult<()> {
+    const PATH: &str = "export_patch_series.nelf";
+    const FILE: &str = "export_patch_series";
+    const DIR: &str = "export_patch_series_patches";
+
+    let _ = fs::remove_dir_all(DIR);
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+
+    for (data, name) in [(b"A", "first"), (b"B", "second"), (b"C", "third")] {
+        fs::write(FILE, data)?;
+        sbvc.commit()?;
+        sbvc.rename(name)?;
+    }
+
+    assert!(sbvc.export_patch_series(2, 1, Path::new(DIR)).is_err());
+    assert!(matches!(
+        sbvc.export_patch_series(99, 99, Path::new(DIR)),
+        Err(SbvcError::VersionNotFound(99))
+    ));
+
+    let paths = sbvc.export_patch_series(0, 3, Path::new(DIR))?;
+    assert_eq!(
+        paths,
+        [
+            Path::new(DIR).join("0001-first.patch"),
+            Path::new(DIR).join("0002-second.patch"),
+            Path::new(DIR).join("0003-third.patch"),
+        ]
+    );
+    assert_eq!(fs::read_dir(DIR)?.count(), 3);
+
+    fs::remove_dir_all(DIR)?;
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn apply_patch_series() -> SbvcResult<()> {
+    const PATH_1: &str = "apply_patch_series_1.nelf";
+    const PATH_2: &str = "apply_patch_series_2.nelf";
+    const FILE: &str = "apply_patch_series";
+    const DIR: &str = "apply_patch_series_patches";
+
+    let _ = fs::remove_dir_all(DIR);
+
+    let mut sbvc = Sbvc::new(PATH_1.into(), FILE.into())?;
+
+    for (data, name) in [(b"AB", "first"), (b"BC", "second"), (b"CD", "third")]
+    {
+        fs::write(FILE, data)?;
+        sbvc.commit()?;
+        sbvc.rename(name)?;
+    }
+
+    sbvc.export_patch_series(0, 3, Path::new(DIR))?;
+
+    let mut other = Sbvc::new(PATH_2.into(), FILE.into())?;
+    other.apply_patch_series(Path::new(DIR))?;
+
+    let sbvc = Sbvc::open(PATH_1.into())?;
+    let other = Sbvc::open(PATH_2.into())?;
+    assert_eq!(other.versions().len(), 4);
+
+    for (version, other_version) in
+        sbvc.versions().iter().zip(other.versions()).skip(1)
+    {
+        assert_eq!(version.id(), other_version.id());
+        assert_eq!(version.base(), other_version.base());
+        assert_eq!(version.name(), other_version.name());
+        assert_eq!(version.date(), other_version.date());
+        assert_eq!(version.difference(), other_version.difference());
+    }
+
+    fs::write(Path::new(DIR).join("0004-broken.patch"), b"|4|\n|3|\n")?;
+    let mut other = Sbvc::new(PATH_2.into(), FILE.into())?;
+    match other.apply_patch_series(Path::new(DIR)) {
+        Err(SbvcError::PatchFailed(path)) => {
+            assert_eq!(path, Path::new(DIR).join("0004-broken.patch"))
+        }
+        _ => panic!("Expected a failed patch"),
+    }
+    assert_eq!(other.versions().len(), 4);
+
+    let mut other = Sbvc::new(PATH_2.into(), FILE.into())?;
+    other.set_pre_commit_hook(|content| {
+        if content == b"BC" {
+            Err("Rejected".to_string())
+        } else {
+            Ok(())
+        }
+    });
+    assert!(matches!(
+        other.apply_patch_series(Path::new(DIR)),
+        Err(SbvcError::HookRejected(error)) if error == "Rejected"
+    ));
+    assert_eq!(other.versions().len(), 2);
+    assert_eq!(Sbvc::open(PATH_2.into())?.versions().len(), 2);
+
+    fs::remove_dir_all(DIR)?;
+    fs::remove_file(PATH_1)?;
+    fs::remove_file(PATH_2)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn working_hash() -> SbvcResult<()> {
+    const PATH: &str = "working_hash.nelf";
+    const FILE: &str = "working_hash";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    assert_eq!(sbvc.working_hash()?, sbvc.content_hash(1)?);
+    assert_ne!(sbvc.working_hash()?, sbvc.content_hash(0)?);
+
+    fs::write(FILE, DATA_2)?;
+    assert_ne!(sbvc.working_hash()?, sbvc.content_hash(1)?);
+    assert!(sbvc.content_hash(2).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn delete_keep_children() -> SbvcResult<()> {
+    const PATH: &str = "delete_keep_children.nelf";
+    const FILE: &str = "delete_keep_children";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+    const DATA_3: &[u8] = b"SOME DATA TO REPLACE WHAT WAS BEFORE";
+    const DATA_4: &[u8] = b"OTHER DATA TO PUT INTO FILE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_3)?;
+    sbvc.commit()?;
+    sbvc.checkout(2, true)?;
+    fs::write(FILE, DATA_4)?;
+    sbvc.commit()?;
+    sbvc.checkout(2, true)?;
+    sbvc.delete_keep_children()?;
+    assert_eq!(sbvc.current().id(), 1);
+    assert_eq!(fs::read(FILE)?, DATA_1);
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(
+        sbvc.versions()
+            .iter()
+            .map(|version| (version.id(), version.base()))
+            .collect::<Vec<_>>(),
+        [(0, 0), (1, 0), (3, 1), (4, 1)]
+    );
+    sbvc.checkout(3, true)?;
+    assert_eq!(fs::read(FILE)?, DATA_3);
+    sbvc.checkout(4, true)?;
+    assert_eq!(fs::read(FILE)?, DATA_4);
+
+    sbvc.checkout(0, true)?;
+    sbvc.delete_keep_children()?;
+    assert_eq!(sbvc.versions().len(), 4);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn tree_diff() -> SbvcResult<()> {
+    const PATH_1: &str = "tree_diff_1.nelf";
+    const PATH_2: &str = "tree_diff_2.nelf";
+    const FILE: &str = "tree_diff";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+    const DATA_3: &[u8] = b"SOME DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH_1.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::copy(PATH_1, PATH_2)?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+
+    let mut other = Sbvc::open(PATH_2.into())?;
+    fs::write(FILE, DATA_3)?;
+    other.commit()?;
+    other.commit()?;
+
+    let tree_diff = sbvc.tree_diff(&other);
+    assert_eq!(tree_diff.only_self(), [2]);
+    assert_eq!(tree_diff.only_other(), [2, 3]);
+    assert_eq!(tree_diff.common(), [(0, 0), (1, 1)]);
+
+    fs::remove_file(PATH_1)?;
+    fs::remove_file(PATH_2)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn commit_from() -> SbvcResult<()> {
+    const PATH: &str = "commit_from.nelf";
+    const FILE: &str = "commit_from";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let _ = fs::remove_file(FILE);
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    assert_eq!(sbvc.commit_from(Cursor::new(DATA))?, 1);
+    assert_eq!(sbvc.current().id(), 1);
+    assert!(fs::metadata(FILE).is_err());
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    sbvc.checkout(1, true)?;
+    assert_eq!(fs::read(FILE)?, DATA);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn text_diff_context() -> SbvcResult<()> {
+    const PATH: &str = "text_diff_context.nelf";
+    const FILE: &str = "text_diff_context";
+    const DATA_1: &[u8] = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+    const DATA_2: &[u8] = b"1\nTWO\n3\n4\n5\n6\n7\n8\n10\n";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+
+    let hunks = sbvc.text_diff_context(1, 2, 1)?;
+    assert_eq!(hunks.len(), 2);
+    assert_eq!(
+        (hunks[0].old_start(), hunks[0].old_len()),
+        (hunks[0].new_start(), hunks[0].new_len())
+    );
+    assert_eq!((hunks[0].old_start(), hunks[0].old_len()), (0, 3));
+    assert_eq!(
+        hunks[0].lines(),
+        [
+            DiffLine::Context("1\n".to_string()),
+            DiffLine::Deletion("2\n".to_string()),
+            DiffLine::Insertion("TWO\n".to_string()),
+            DiffLine::Context("3\n".to_string()),
+        ]
+    );
+    assert_eq!((hunks[1].old_start(), hunks[1].old_len()), (7, 3));
+    assert_eq!((hunks[1].new_start(), hunks[1].new_len()), (7, 2));
+    assert_eq!(
+        hunks[1].lines(),
+        [
+            DiffLine::Context("8\n".to_string()),
+            DiffLine::Deletion("9\n".to_string()),
+            DiffLine::Context("10\n".to_string()),
+        ]
+    );
+
+    let hunks = sbvc.text_diff_context(1, 2, 3)?;
+    assert_eq!(hunks.len(), 1);
+    assert_eq!((hunks[0].old_start(), hunks[0].old_len()), (0, 10));
+    assert_eq!((hunks[0].new_start(), hunks[0].new_len()), (0, 9));
+
+    assert!(sbvc.text_diff_context(1, 1, 3)?.is_empty());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn new_with_initial_commit() -> SbvcResult<()> {
+    const PATH: &str = "new_with_initial_commit.nelf";
+    const FILE: &str = "new_with_initial_commit";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let _ = fs::remove_file(FILE);
+
+    let sbvc = Sbvc::new_with_initial_commit(PATH.into(), FILE.into())?;
+    assert_eq!(sbvc.versions().len(), 1);
+
+    fs::write(FILE, DATA)?;
+
+    let mut sbvc = Sbvc::new_with_initial_commit(PATH.into(), FILE.into())?;
+    assert_eq!(sbvc.versions().len(), 2);
+    assert_eq!(sbvc.current().id(), 1);
+    assert!(!sbvc.is_changed()?);
+
+    fs::write(FILE, b"")?;
+    sbvc.checkout(1, true)?;
+    assert_eq!(fs::read(FILE)?, DATA);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn changed_ranges() -> SbvcResult<()> {
+    const PATH: &str = "changed_ranges.nelf";
+    const FILE: &str = "changed_ranges";
+    const DATA_1: &[u8] = b"ABCDEFGH";
+    const DATA_2: &[u8] = b"AXCDFGHZ";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.changed_ranges(1, 2)?, [1..2, 4..4, 7..8]);
+    assert_eq!(sbvc.changed_ranges(0, 1)?, vec![(0..8)]);
+    assert!(sbvc.changed_ranges(2, 2)?.is_empty());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn open_with_limits() -> SbvcResult<()> {
+    const PATH: &str = "open_with_limits.nelf";
+    const FILE: &str = "open_with_limits";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA)?;
+    sbvc.commit()?;
+    sbvc.commit()?;
+
+    let limits = Limits { max_versions: 3, ..Limits::default() };
+    assert_eq!(
+        Sbvc::open_with_limits(PATH.into(), &limits)?.versions().len(),
+        3
+    );
+
+    let limits = Limits { max_versions: 2, ..Limits::default() };
+    assert!(matches!(
+        Sbvc::open_with_limits(PATH.into(), &limits),
+        Err(SbvcError::TooLarge(_))
+    ));
+
+    let limits = Limits { max_file_size: 16, ..Limits::default() };
+    assert!(matches!(
+        Sbvc::open_with_limits(PATH.into(), &limits),
+        Err(SbvcError::TooLarge(_))
+    ));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn select() -> SbvcResult<()> {
+    const PATH: &str = "select.nelf";
+    const FILE: &str = "select";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+    sbvc.select(1)?;
+    assert!(sbvc.select(3).is_err());
+    assert_eq!(fs::read(FILE)?, DATA_2);
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.current().id(), 1);
+    assert_eq!(fs::read(FILE)?, DATA_2);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn source_path() -> SbvcResult<()> {
+    const PATH: &str = "source_path.nelf";
+    const FILE_1: &str = "source_path_1";
+    const FILE_2: &str = "source_path_2";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE_1.into())?;
+    fs::write(FILE_1, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+    fs::rename(FILE_1, FILE_2)?;
+    sbvc.set_file(FILE_2.into())?;
+    sbvc.commit()?;
+    sbvc.commit_from(Cursor::new(b"SOME DATA"))?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(
+        sbvc.versions()
+            .iter()
+            .map(|version| version.source_path())
+            .collect::<Vec<_>>(),
+        [None, Some(Path::new(FILE_1)), Some(Path::new(FILE_2)), None]
+    );
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE_2)?;
+
+    Ok(())
+}
+
+#[test]
+fn largest_versions() -> SbvcResult<()> {
+    const PATH: &str = "largest_versions.nelf";
+    const FILE: &str = "largest_versions";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"AAAA")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"AAAABBBBBBBB")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"AABBBBBBBB")?;
+    sbvc.commit()?;
+
+    assert_eq!(
+        sbvc.versions()
+            .iter()
+            .map(|version| version.stored_size())
+            .collect::<Vec<_>>(),
+        [0, 4, 8, 2]
+    );
+    assert_eq!(sbvc.largest_versions(2), [2, 1]);
+    assert_eq!(sbvc.largest_versions(10), [2, 1, 3, 0]);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn utf8_field() -> SbvcResult<()> {
+    const PATH: &str = "utf8_field.nelf";
+    const FILE: &str = "utf8_field";
+
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.commit()?;
+    sbvc.rename("XNAMEX")?;
+
+    let mut source = fs::read(PATH)?;
+    let index =
+        source.windows(6).position(|window| window == b"XNAMEX").unwrap();
+    source[index + 1] = 0xFF;
+    fs::write(PATH, source)?;
+
+    match Sbvc::open(PATH.into()) {
+        Err(SbvcError::Utf8Field(field, _)) => {
+            assert_eq!(field, "name of version 1")
+        }
+        _ => panic!("Expected a UTF-8 field error"),
+    }
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn move_current_to() -> SbvcResult<()> {
+    const PATH: &str = "move_current_to.nelf";
+    const FILE: &str = "move_current_to";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+    const DATA_3: &[u8] = b"SOME DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+    sbvc.checkout(1, false)?;
+    fs::write(FILE, DATA_3)?;
+    sbvc.commit()?;
+    sbvc.move_current_to(2)?;
+    assert_eq!(sbvc.current().base(), 2);
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.current().id(), 3);
+    assert_eq!(sbvc.current().base(), 2);
+    sbvc.checkout(3, true)?;
+    assert_eq!(fs::read(FILE)?, DATA_3);
+
+    sbvc.checkout(1, false)?;
+    assert!(matches!(
+        sbvc.move_current_to(3),
+        Err(SbvcError::InvalidOperation(_))
+    ));
+    sbvc.checkout(0, false)?;
+    assert!(matches!(
+        sbvc.move_current_to(1),
+        Err(SbvcError::InvalidOperation(_))
+    ));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn commit_ranges() -> SbvcResult<()> {
+    const PATH: &str = "commit_ranges.nelf";
+    const FILE: &str = "commit_ranges";
+    const DATA_1: &[u8] = b"ABCD----EFGH----IJKL";
+    const DATA_2: &[u8] = b"AXCD----EFYH----IJ";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    assert_eq!(sbvc.commit_ranges(&[(0..4), (16..18)])?, 2);
+    assert!(sbvc.is_changed()?);
+    assert_eq!(fs::read(FILE)?, DATA_2);
+    assert_eq!(sbvc.changed_ranges(1, 2)?, [1..2, 18..18]);
+
+    sbvc.commit()?;
+    assert_eq!(sbvc.changed_ranges(2, 3)?, vec![(10..11)]);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn find_versions_containing() -> SbvcResult<()> {
+    const PATH: &str = "find_versions_containing.nelf";
+    const FILE: &str = "find_versions_containing";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+    const DATA_3: &[u8] = b"some other data";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+    sbvc.checkout(1, false)?;
+    fs::write(FILE, DATA_3)?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.find_versions_containing(b"DATA", false), [1, 2]);
+    assert_eq!(sbvc.find_versions_containing(b"OTHER", false), [2]);
+    assert_eq!(sbvc.find_versions_containing(b"OTHER", true), [2, 3]);
+    assert!(sbvc.find_versions_containing(b"MISSING", true).is_empty());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn beside() -> SbvcResult<()> {
+    const PATH: &str = "beside.txt.sbvc";
+    const FILE: &str = "beside.txt";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let mut sbvc = Sbvc::new_beside(FILE.into())?;
+    assert_eq!(sbvc.path(), Path::new(PATH));
+    fs::write(FILE, DATA)?;
+    sbvc.commit()?;
+
+    let sbvc = Sbvc::open_beside(FILE.into())?;
+    assert_eq!(sbvc.path(), Path::new(PATH));
+    assert_eq!(sbvc.file(), Path::new(FILE));
+    assert_eq!(sbvc.current().id(), 1);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn verify_reconstruction() -> SbvcResult<()> {
+    const PATH: &str = "verify_reconstruction.nelf";
+    const FILE: &str = "verify_reconstruction";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"0123456789")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"01234")?;
+    sbvc.commit()?;
+    sbvc.verify_reconstruction(2)?;
+
+    let mut source = fs::read(PATH)?;
+    let index = source.windows(4).position(|window| window == b"|10|").unwrap();
+    source[index + 2] = b'2';
+    fs::write(PATH, source)?;
+
+    // Differences out of bounds of their bases are caught when opening
+    assert!(matches!(
+        Sbvc::open(PATH.into()),
+        Err(SbvcError::InvalidFormat(_))
+    ));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn coalesce_window() -> SbvcResult<()> {
+    const PATH: &str = "coalesce_window.nelf";
+    const FILE: &str = "coalesce_window";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.set_coalesce_window(Some(Duration::from_secs(60)), false);
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+    assert_eq!(sbvc.versions().len(), 2);
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.versions().len(), 2);
+    fs::write(FILE, DATA_1)?;
+    sbvc.checkout(1, true)?;
+    assert_eq!(fs::read(FILE)?, DATA_2);
+
+    sbvc.commit()?;
+    assert_eq!(sbvc.versions().len(), 3);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn similarity() -> SbvcResult<()> {
+    const PATH: &str = "similarity.nelf";
+    const FILE: &str = "similarity";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"AAAABBBB")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"CCCCDDDD")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"CCCCDDDDEEEEFFFF")?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.similarity(0, 0)?, 1.0);
+    assert_eq!(sbvc.similarity(1, 1)?, 1.0);
+    assert_eq!(sbvc.similarity(1, 2)?, 0.0);
+    assert_eq!(sbvc.similarity(2, 3)?, 0.5);
+    assert_eq!(sbvc.similarity(0, 3)?, 0.0);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn verify_file() -> SbvcResult<()> {
+    const PATH: &str = "verify_file.nelf";
+    const FILE: &str = "verify_file";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+
+    for index in 0..64 {
+        fs::write(FILE, vec![index; 512])?;
+        sbvc.commit()?;
+    }
+
+    Sbvc::verify_file(Path::new(PATH))?;
+
+    let mut source = fs::read(PATH)?;
+    let index =
+        source.windows(4).position(|window| window == b"|7|\n").unwrap();
+    source[index + 1] = b'9';
+    let index =
+        source.windows(4).position(|window| window == b"|7|\n").unwrap();
+    source[index + 1] = b'9';
+    fs::write(PATH, source)?;
+    assert!(matches!(
+        Sbvc::verify_file(Path::new(PATH)),
+        Err(SbvcError::InvalidFormat(_))
+    ));
+
+    fs::remove_file(PATH)?;
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"FIRST")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"SECOND")?;
+    sbvc.commit()?;
+    Sbvc::verify_file(Path::new(PATH))?;
+
+    // Make version 1 based on version 2, which is based on version 1
+    let mut source = fs::read(PATH)?;
+    let index =
+        source.windows(8).position(|window| window == b"|1|\n|0|\n").unwrap();
+    source[index + 5] = b'2';
+    fs::write(PATH, source)?;
+    assert!(matches!(
+        Sbvc::verify_file(Path::new(PATH)),
+        Err(SbvcError::InvalidFormat(message)) if message.contains("cycle")
+    ));
+    assert!(matches!(
+        Sbvc::open(PATH.into()),
+        Err(SbvcError::InvalidFormat(_))
+    ));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn diff_files() -> SbvcResult<()> {
+    const FILE_1: &str = "diff_files_1";
+    const FILE_2: &str = "diff_files_2";
+
+    fs::write(FILE_1, b"BANANA")?;
+    fs::write(FILE_2, b"ATANA")?;
+
+    let difference = Sbvc::diff_files(Path::new(FILE_1), Path::new(FILE_2))?;
+    assert_eq!(difference.deletions, [0..1, 2..3]);
+    assert_eq!(difference.insertions.len(), 1);
+    assert_eq!(difference.insertions[0].start, 1);
+    assert_eq!(difference.insertions[0].data, b"T");
+
+    fs::remove_file(FILE_1)?;
+    fs::remove_file(FILE_2)?;
+
+    Ok(())
+}
+
+#[test]
+fn self_tracking() -> SbvcResult<()> {
+    const PATH: &str = "self_tracking.nelf";
+    const FILE: &str = "self_tracking";
+
+    assert!(matches!(
+        Sbvc::new(PATH.into(), PATH.into()),
+        Err(SbvcError::SelfTracking)
+    ));
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    assert!(matches!(
+        sbvc.set_file(format!("./{}", PATH).into()),
+        Err(SbvcError::SelfTracking)
+    ));
+    assert_eq!(sbvc.file(), Path::new(FILE));
+
+    fs::remove_file(PATH)?;
+
+    Ok(())
+}
+
+#[test]
+fn branch_point_of() -> SbvcResult<()> {
+    const PATH: &str = "branch_point_of.nelf";
+    const FILE: &str = "branch_point_of";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+
+    for data in [b"A", b"B", b"C"] {
+        fs::write(FILE, data)?;
+        sbvc.commit()?;
+    }
+
+    assert_eq!(sbvc.branch_point_of(3)?, 0);
+    assert_eq!(sbvc.branch_point_of(0)?, 0);
+
+    sbvc.checkout(1, false)?;
+
+    for data in [b"D", b"E"] {
+        fs::write(FILE, data)?;
+        sbvc.commit()?;
+    }
+
+    assert_eq!(sbvc.branch_point_of(5)?, 1);
+    assert_eq!(sbvc.branch_point_of(3)?, 1);
+    assert_eq!(sbvc.branch_point_of(1)?, 0);
+    assert!(sbvc.branch_point_of(6).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn repair_next() -> SbvcResult<()> {
+    const PATH: &str = "repair_next.nelf";
+    const FILE: &str = "repair_next";
+
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.commit()?;
+    sbvc.commit()?;
+
+    let source = fs::read(PATH)?;
+    let mut lines = source.splitn(4, |&ch| ch == b'\n');
+    let mut corrupted = Vec::new();
+    corrupted.extend_from_slice(lines.next().unwrap());
+    corrupted.push(b'\n');
+    corrupted.extend_from_slice(lines.next().unwrap());
+    corrupted.extend_from_slice(b"\n|1|\n");
+    corrupted.extend_from_slice(lines.nth(1).unwrap());
+    fs::write(PATH, corrupted)?;
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    sbvc.commit()?;
+    assert_eq!(
+        sbvc.versions().iter().map(|version| version.id()).collect::<Vec<_>>(),
+        [0, 1, 2, 3]
+    );
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn child_count() -> SbvcResult<()> {
+    const PATH: &str = "child_count.nelf";
+    const FILE: &str = "child_count";
+
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.commit()?;
+    sbvc.commit()?;
+    sbvc.checkout(1, false)?;
+    sbvc.commit()?;
+    sbvc.checkout(1, false)?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.child_count(0)?, 1);
+    assert_eq!(sbvc.child_count(1)?, 3);
+    assert_eq!(sbvc.child_count(4)?, 0);
+    assert!(sbvc.child_count(5).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn snapshot() -> SbvcResult<()> {
+    const PATH: &str = "snapshot.nelf";
+    const FILE: &str = "snapshot";
+
+    fs::write(FILE, b"AAAAAAAA")?;
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.commit()?;
+    fs::write(FILE, b"BBBBBBBBBB")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"BBBBBCBBBB")?;
+    sbvc.commit()?;
+
+    assert!(!sbvc.versions()[1].is_snapshot());
+    assert!(sbvc.versions()[2].is_snapshot());
+    assert_eq!(sbvc.versions()[2].difference().deletions, vec![0..8]);
+    assert_eq!(sbvc.versions()[2].difference().insertions.len(), 1);
+    assert!(!sbvc.versions()[3].is_snapshot());
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    assert!(sbvc.versions()[2].is_snapshot());
+    sbvc.checkout(2, true)?;
+    assert_eq!(fs::read(FILE)?, b"BBBBBBBBBB");
+    sbvc.checkout(3, true)?;
+    assert_eq!(fs::read(FILE)?, b"BBBBBCBBBB");
+    sbvc.verify_reconstruction(3)?;
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn undo_delete() -> SbvcResult<()> {
+    const PATH: &str = "undo_delete.nelf";
+    const FILE: &str = "undo_delete";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"FIRST")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"SECOND")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"THIRD")?;
+    sbvc.commit()?;
+    sbvc.checkout(2, false)?;
+    fs::write(FILE, b"FOURTH")?;
+    sbvc.commit()?;
+    sbvc.checkout(2, true)?;
+
+    assert!(sbvc.undo_delete().is_err());
+    sbvc.delete()?;
+    assert_eq!(sbvc.versions().len(), 2);
+    assert_eq!(fs::read(FILE)?, b"FIRST");
+
+    sbvc.undo_delete()?;
+    assert!(sbvc.undo_delete().is_err());
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    let mut ids: Vec<_> =
+        sbvc.versions().iter().map(|version| version.id()).collect();
+    ids.sort();
+    assert_eq!(ids, [0, 1, 2, 3, 4]);
+
+    for (id, content) in [(2, &b"SECOND"[..]), (3, b"THIRD"), (4, b"FOURTH")] {
+        sbvc.checkout(id, true)?;
+        assert_eq!(fs::read(FILE)?, content);
+    }
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn undo_delete_after_changes() -> SbvcResult<()> {
+    const PATH: &str = "undo_delete_after_changes.nelf";
+    const FILE: &str = "undo_delete_after_changes";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    let ids = |sbvc: &Sbvc| -> Vec<u32> {
+        sbvc.versions().iter().map(|version| version.id()).collect()
+    };
+
+    for content in ["FIRST", "SECOND", "THIRD", "FOURTH"] {
+        fs::write(FILE, content)?;
+        sbvc.commit()?;
+    }
+
+    sbvc.checkout(3, true)?;
+    sbvc.delete_version(4, false)?;
+    sbvc.delete_keep_children()?;
+    assert!(sbvc.undo_delete().is_err());
+    assert_eq!(ids(&sbvc), [0, 1, 2]);
+
+    for content in ["THIRD", "FOURTH", "FIFTH"] {
+        fs::write(FILE, content)?;
+        sbvc.commit()?;
+    }
+
+    sbvc.checkout(1, true)?;
+    sbvc.delete_version(7, false)?;
+    sbvc.squash(1, 6)?;
+    assert!(sbvc.undo_delete().is_err());
+    assert_eq!(ids(&sbvc), [0, 1, 6]);
+
+    let leaves: Vec<_> =
+        sbvc.leaves().iter().map(|version| version.id()).collect();
+    assert_eq!(leaves, [6]);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn open_from() -> SbvcResult<()> {
+    const PATH: &str = "open_from.nelf";
+    const FILE: &str = "open_from";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA)?;
+    sbvc.commit()?;
+
+    let source = fs::read(PATH)?;
+    fs::remove_file(PATH)?;
+
+    let mut sbvc = Sbvc::open_from(Cursor::new(&source), PATH.into(), None)?;
+    assert_eq!(sbvc.file(), Path::new(FILE));
+    assert_eq!(sbvc.versions().len(), 2);
+    assert_eq!(sbvc.current().id(), 1);
+    assert!(fs::metadata(PATH).is_err());
+
+    fs::write(FILE, b"")?;
+    sbvc.checkout(1, true)?;
+    assert_eq!(fs::read(FILE)?, DATA);
+
+    let sbvc = Sbvc::open_from(
+        Cursor::new(&source),
+        PATH.into(),
+        Some("open_from_other".into()),
+    )?;
+    assert_eq!(sbvc.file(), Path::new("open_from_other"));
+
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn relocate() -> SbvcResult<()> {
+    const PATH: &str = "relocate.nelf";
+    const FILE_1: &str = "relocate_1";
+    const FILE_2: &str = "relocate_2";
+    const FILE_3: &str = "relocate_3";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE_1.into())?;
+    fs::write(FILE_1, DATA)?;
+    sbvc.commit()?;
+
+    sbvc.relocate(FILE_2.into(), false)?;
+    assert_eq!(sbvc.versions().len(), 2);
+    assert_eq!(sbvc.file(), Path::new(FILE_2));
+
+    sbvc.relocate(FILE_3.into(), true)?;
+    assert_eq!(sbvc.versions().len(), 3);
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.file(), Path::new(FILE_3));
+    assert_eq!(sbvc.current().base(), 1);
+    assert_eq!(sbvc.current().moved_from(), Some(Path::new(FILE_2)));
+    assert_eq!(sbvc.current().source_path(), Some(Path::new(FILE_3)));
+    assert_eq!(sbvc.versions()[1].moved_from(), None);
+    assert!(sbvc.current().difference().insertions.is_empty());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE_1)?;
+
+    Ok(())
+}
+
+#[test]
+fn first_commit() -> SbvcResult<()> {
+    const PATH: &str = "first_commit.nelf";
+    const FILE: &str = "first_commit";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA)?;
+    sbvc.commit()?;
+
+    let difference = sbvc.versions()[1].difference();
+    assert!(difference.deletions.is_empty());
+    assert_eq!(difference.insertions.len(), 1);
+    assert_eq!(difference.insertions[0].start, 0);
+    assert_eq!(difference.insertions[0].data, DATA);
+    assert!(!sbvc.versions()[1].is_snapshot());
+
+    fs::write(FILE, b"")?;
+    sbvc.checkout(1, true)?;
+    assert_eq!(fs::read(FILE)?, DATA);
+    sbvc.verify_reconstruction(1)?;
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn to_dot() -> SbvcResult<()> {
+    const PATH: &str = "to_dot.nelf";
+    const FILE: &str = "to_dot";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"SOME OTHER DATA")?;
+    sbvc.commit()?;
+    sbvc.rename("say \"hi\"")?;
+
+    let dot = sbvc.to_dot(false);
+    assert!(dot.starts_with("digraph sbvc {\n"));
+    assert!(dot.contains("    2 [label=\"2: say \\\"hi\\\"\"];\n"));
+    assert!(dot.contains("    0 -> 1;\n"));
+    assert!(dot.contains("    1 -> 2;\n"));
+    assert!(!dot.contains("penwidth"));
+
+    let dot = sbvc.to_dot(true);
+    for version in &sbvc.versions()[1..] {
+        assert!(dot.contains(&format!(
+            "    {} -> {} [label=\"{}\", ",
+            version.base(),
+            version.id(),
+            version.stored_size()
+        )));
+    }
+    assert!(dot.contains("[label=\"9\", "));
+    assert!(dot.contains("[label=\"6\", "));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn open_lenient_truncated() -> SbvcResult<()> {
+    const PATH: &str = "open_lenient_truncated.nelf";
+    const FILE: &str = "open_lenient_truncated";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA)?;
+    sbvc.commit()?;
+
+    let source = fs::read(PATH)?;
+    let mut truncated = Vec::new();
+    for line in source.split(|&ch| ch == b'\n').take(3) {
+        truncated.extend_from_slice(line);
+        truncated.push(b'\n');
+    }
+    fs::write(PATH, truncated)?;
+
+    assert!(Sbvc::open(PATH.into()).is_err());
+
+    let (mut sbvc, _) = Sbvc::open_lenient(PATH.into())?;
+    assert_eq!(sbvc.versions().len(), 1);
+    assert_eq!(sbvc.current().id(), 0);
+
+    sbvc.commit()?;
+    sbvc.checkout(0, true)?;
+    sbvc.checkout(sbvc.versions()[1].id(), true)?;
+    assert_eq!(fs::read(FILE)?, DATA);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn aligned_edits() -> SbvcResult<()> {
+    const PATH: &str = "aligned_edits.nelf";
+    const FILE: &str = "aligned_edits";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"ABCDEFGH")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"AXBCDFYH")?;
+    sbvc.commit()?;
+
+    let edits: Vec<_> = sbvc
+        .aligned_edits(1, 2)?
+        .iter()
+        .map(|edit| (edit.old_range(), edit.new_range(), edit.kind()))
+        .collect();
+    assert_eq!(
+        edits,
+        [
+            (1..1, 1..2, EditKind::Insertion),
+            (4..5, 5..5, EditKind::Deletion),
+            (6..7, 6..7, EditKind::Replacement),
+        ]
+    );
+    assert!(sbvc.aligned_edits(2, 2)?.is_empty());
+    assert!(sbvc.aligned_edits(1, 3).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn verify_signatures() -> SbvcResult<()> {
+    const PATH: &str = "verify_signatures.nelf";
+    const FILE: &str = "verify_signatures";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"UNSIGNED DATA")?;
+    sbvc.commit()?;
+    assert!(sbvc.verify_signatures().is_err());
+
+    sbvc.set_signing_key(Some(b"KEY".to_vec()));
+    fs::write(FILE, b"SIGNED DATA")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"MORE SIGNED DATA")?;
+    sbvc.commit()?;
+
+    assert!(!sbvc.versions()[1].is_signed());
+    assert!(sbvc.versions()[2].is_signed());
+    assert!(sbvc.verify_signatures()?.is_empty());
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    sbvc.set_signing_key(Some(b"KEY".to_vec()));
+    assert!(sbvc.verify_signatures()?.is_empty());
+    sbvc.set_signing_key(Some(b"OTHER KEY".to_vec()));
+    assert_eq!(sbvc.verify_signatures()?, [2, 3]);
+
+    let mut source = fs::read(PATH)?;
+    let index = source.windows(4).position(|window| window == b"MORE").unwrap();
+    source[index..index + 4].copy_from_slice(b"LESS");
+    fs::write(PATH, source)?;
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    sbvc.set_signing_key(Some(b"KEY".to_vec()));
+    assert_eq!(sbvc.verify_signatures()?, [3]);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn apply_patch_series_signatures() -> SbvcResult<()> {
+    const PATH_1: &str = "apply_patch_series_signatures_1.nelf";
+    const PATH_2: &str = "apply_patch_series_signatures_2.nelf";
+    const FILE: &str = "apply_patch_series_signatures";
+    const DIR: &str = "apply_patch_series_signatures_patches";
+
+    let _ = fs::remove_dir_all(DIR);
+
+    let mut sbvc = Sbvc::new(PATH_1.into(), FILE.into())?;
+    sbvc.set_signing_key(Some(b"KEY".to_vec()));
+    fs::write(FILE, b"SIGNED DATA")?;
+    sbvc.commit()?;
+    sbvc.export_patch_series(0, 1, Path::new(DIR))?;
+
+    // The patch is applied on top of a different base
+    let mut other = Sbvc::new(PATH_2.into(), FILE.into())?;
+    fs::write(FILE, b"OTHER DATA")?;
+    other.commit()?;
+    other.apply_patch_series(Path::new(DIR))?;
+    assert!(!other.current().is_signed());
+
+    other.set_signing_key(Some(b"KEY".to_vec()));
+    other.apply_patch_series(Path::new(DIR))?;
+    assert!(other.current().is_signed());
+    assert!(other.verify_signatures()?.is_empty());
+
+    fs::remove_dir_all(DIR)?;
+    fs::remove_file(PATH_1)?;
+    fs::remove_file(PATH_2)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn rebase_unsigned() -> SbvcResult<()> {
+    const PATH: &str = "rebase_unsigned.nelf";
+    const FILE: &str = "rebase_unsigned";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.set_signing_key(Some(b"KEY".to_vec()));
+    fs::write(FILE, b"SIGNED DATA")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"MORE SIGNED DATA")?;
+    sbvc.commit()?;
+
+    sbvc.set_signing_key(None);
+    sbvc.move_current_to(0)?;
+    assert!(!sbvc.current().is_signed());
+
+    sbvc.set_signing_key(Some(b"KEY".to_vec()));
+    assert!(sbvc.verify_signatures()?.is_empty());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn first_version_with_content() -> SbvcResult<()> {
+    const PATH: &str = "first_version_with_content.nelf";
+    const FILE: &str = "first_version_with_content";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.first_version_with_content(DATA_1), Some(1));
+    assert_eq!(sbvc.first_version_with_content(DATA_2), Some(2));
+    assert_eq!(sbvc.first_version_with_content(b""), Some(0));
+    assert_eq!(sbvc.first_version_with_content(b"NEVER COMMITTED"), None);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn open_lazy() -> SbvcResult<()> {
+    const PATH: &str = "open_lazy.nelf";
+    const FILE: &str = "open_lazy";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    sbvc.rename("first")?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+    sbvc.checkout(1, false)?;
+    fs::write(FILE, b"SOME DATA")?;
+    sbvc.commit()?;
+
+    let eager = Sbvc::open(PATH.into())?;
+    let mut sbvc = Sbvc::open_lazy(PATH.into())?;
+    assert!(sbvc.versions().iter().all(|version| !version.is_loaded()));
+
+    for (lazy, eager) in sbvc.versions().iter().zip(eager.versions()) {
+        assert_eq!(lazy.id(), eager.id());
+        assert_eq!(lazy.base(), eager.base());
+        assert_eq!(lazy.name(), eager.name());
+        assert_eq!(lazy.date(), eager.date());
+        assert_eq!(lazy.stored_size(), eager.stored_size());
+    }
+    assert_eq!(sbvc.versions()[1].name(), "first");
+    assert!(sbvc.versions().iter().all(|version| !version.is_loaded()));
+
+    sbvc.checkout(2, true)?;
+    assert_eq!(fs::read(FILE)?, DATA_2);
+    assert!(sbvc.versions()[1].is_loaded());
+    assert!(sbvc.versions()[2].is_loaded());
+    assert!(!sbvc.versions()[3].is_loaded());
+
+    sbvc.rename("second")?;
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    sbvc.checkout(3, true)?;
+    assert_eq!(fs::read(FILE)?, b"SOME DATA");
+    assert_eq!(sbvc.versions()[2].name(), "second");
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn edit_distance() -> SbvcResult<()> {
+    const PATH: &str = "edit_distance.nelf";
+    const FILE: &str = "edit_distance";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"ABCDEFGH")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"AXBCDFYH")?;
+    sbvc.commit()?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.edit_distance(1, 2)?, 4);
+    assert_eq!(sbvc.edit_distance(2, 1)?, 4);
+    assert_eq!(sbvc.edit_distance(0, 1)?, 8);
+    assert_eq!(sbvc.edit_distance(2, 3)?, 0);
+    assert_eq!(sbvc.edit_distance(1, 1)?, 0);
+    assert!(sbvc.edit_distance(1, 4).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn commit_working() -> SbvcResult<()> {
+    const PATH: &str = "commit_working.nelf";
+    const FILE: &str = "commit_working";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA)?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.edit(), DATA);
+    sbvc.edit().extend_from_slice(b" AND MORE");
+    assert_eq!(sbvc.commit_working()?, 2);
+    sbvc.edit().truncate(4);
+    assert_eq!(sbvc.commit_working()?, 3);
+    assert_eq!(fs::read(FILE)?, DATA);
+
+    sbvc.edit().clear();
+    sbvc.checkout(2, false)?;
+    assert_eq!(sbvc.edit(), b"SOME DATA TO PUT INTO FILE AND MORE");
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    sbvc.checkout(3, true)?;
+    assert_eq!(fs::read(FILE)?, b"SOME");
+    assert_eq!(sbvc.current().base(), 2);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn not_a_tree() -> SbvcResult<()> {
+    const PATH: &str = "not_a_tree.nelf";
+    const FILE: &str = "not_a_tree";
+
+    fs::write(FILE, [0x7F, b'E', b'L', b'F', 0x02, 0x01, 0xFF, 0x00, 0x9C])?;
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.commit()?;
+
+    assert!(matches!(
+        Sbvc::open(FILE.into()),
+        Err(SbvcError::NotATree(path)) if path == Path::new(FILE)
+    ));
+
+    fs::write(FILE, b"SOME TEXT THAT IS NOT A TREE")?;
+    assert!(matches!(Sbvc::open(FILE.into()), Err(SbvcError::NotATree(_))));
+
+    fs::write(FILE, b"")?;
+    assert!(matches!(Sbvc::open(FILE.into()), Err(SbvcError::NotATree(_))));
+
+    Sbvc::open(PATH.into())?;
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn checkout_with_backup() -> SbvcResult<()> {
+    const PATH: &str = "checkout_with_backup.nelf";
+    const FILE: &str = "checkout_with_backup";
+    const BACKUP: &str = "checkout_with_backup.bak";
+    const OTHER_BACKUP: &str = "checkout_with_backup_other";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+
+    assert!(!sbvc.checkout_with_backup(1, None)?);
+    assert!(fs::metadata(BACKUP).is_err());
+
+    fs::write(FILE, DATA_2)?;
+    assert!(sbvc.checkout_with_backup(1, None)?);
+    assert_eq!(fs::read(BACKUP)?, DATA_2);
+    assert_eq!(fs::read(FILE)?, DATA_1);
+
+    assert!(sbvc.checkout_with_backup(0, Some(Path::new(OTHER_BACKUP)))?);
+    assert_eq!(fs::read(OTHER_BACKUP)?, DATA_1);
+    assert_eq!(fs::read(FILE)?, b"");
+
+    fs::remove_file(FILE)?;
+    assert!(!sbvc.checkout_with_backup(1, None)?);
+    assert_eq!(fs::read(FILE)?, DATA_1);
+    assert!(sbvc.checkout_with_backup(2, None).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+    fs::remove_file(BACKUP)?;
+    fs::remove_file(OTHER_BACKUP)?;
+
+    Ok(())
+}
+
+#[test]
+fn parent_hash() -> SbvcResult<()> {
+    const PATH: &str = "parent_hash.nelf";
+    const FILE: &str = "parent_hash";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"ABCDEFGH")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"ABCDEFGHIJ")?;
+    sbvc.commit()?;
+
+    let mut source = fs::read(PATH)?;
+    let index =
+        source.windows(8).position(|window| window == b"ABCDEFGH").unwrap();
+    source[index] = b'X';
+    fs::write(PATH, source)?;
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    sbvc.checkout(1, true)?;
+    assert_eq!(fs::read(FILE)?, b"XBCDEFGH");
+
+    match sbvc.checkout(2, true) {
+        Err(SbvcError::InvalidFormat(message)) => {
+            assert!(message.contains("version with ID 2"))
+        }
+        _ => panic!("Expected an invalid format error"),
+    }
+    assert_eq!(fs::read(FILE)?, b"XBCDEFGH");
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn unified_diff() -> SbvcResult<()> {
+    const PATH: &str = "unified_diff.nelf";
+    const FILE: &str = "unified_diff";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"1\n2\n3\n4\n5\n6\n")?;
+    sbvc.commit()?;
+    sbvc.rename("old")?;
+    fs::write(FILE, b"1\nTWO\n3\n4\n5\n6")?;
+    sbvc.commit()?;
+    sbvc.rename("new")?;
+
+    assert_eq!(
+        sbvc.unified_diff(1, 2, 1)?,
+        "--- old\n+++ new\n\
+        @@ -1,3 +1,3 @@\n 1\n-2\n+TWO\n 3\n\
+        @@ -5,2 +5,2 @@\n 5\n-6\n+6\n\\ No newline at end of file\n"
+    );
+    assert_eq!(sbvc.unified_diff(2, 2, 3)?, "--- new\n+++ new\n");
+
+    fs::write(FILE, [0xFF])?;
+    sbvc.commit()?;
+    assert!(sbvc.unified_diff(2, 3, 1).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn commit_onto() -> SbvcResult<()> {
+    const PATH: &str = "commit_onto.nelf";
+    const FILE: &str = "commit_onto";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+    const DATA_3: &[u8] = b"SOME DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+
+    fs::write(FILE, DATA_3)?;
+    assert_eq!(sbvc.commit_onto(1)?, 3);
+    assert!(matches!(sbvc.commit_onto(7), Err(SbvcError::VersionNotFound(7))));
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.current().id(), 3);
+    assert_eq!(sbvc.current().base(), 1);
+    assert_eq!(sbvc.child_count(1)?, 2);
+
+    sbvc.checkout(2, true)?;
+    assert_eq!(fs::read(FILE)?, DATA_2);
+    sbvc.checkout(3, true)?;
+    assert_eq!(fs::read(FILE)?, DATA_3);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn reload_if_changed() -> SbvcResult<()> {
+    const PATH: &str = "reload_if_changed.nelf";
+    const FILE: &str = "reload_if_changed";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+
+    let mut reader = Sbvc::open(PATH.into())?;
+    assert!(!reader.reload_if_changed()?);
+
+    fs::write(PATH, fs::read(PATH)?)?;
+    assert!(!reader.reload_if_changed()?);
+
+    sbvc.commit()?;
+    sbvc.rename("renamed")?;
+    assert!(reader.reload_if_changed()?);
+    assert_eq!(reader.versions().len(), 3);
+    assert_eq!(reader.current().name(), "renamed");
+    assert!(!reader.reload_if_changed()?);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn ordinal() -> SbvcResult<()> {
+    const PATH: &str = "ordinal.nelf";
+    const FILE: &str = "ordinal";
+
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.commit()?;
+    sbvc.commit()?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.ordinal(0)?, 1);
+    assert_eq!(sbvc.ordinal(3)?, 4);
+
+    sbvc.checkout(2, false)?;
+    sbvc.delete_keep_children()?;
+    sbvc.checkout(3, false)?;
+    sbvc.commit()?;
+
+    assert_eq!(
+        sbvc.versions().iter().map(|version| version.id()).collect::<Vec<_>>(),
+        [0, 1, 3, 4]
+    );
+    assert_eq!(sbvc.ordinal(1)?, 2);
+    assert_eq!(sbvc.ordinal(3)?, 3);
+    assert_eq!(sbvc.ordinal(4)?, 4);
+    assert!(sbvc.ordinal(2).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn is_changed() -> SbvcResult<()> {
+    const PATH: &str = "is_changed.nelf";
+    const FILE: &str = "is_changed";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+    assert!(!sbvc.is_changed()?);
+
+    fs::write(FILE, b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE")?;
+    assert!(sbvc.is_changed()?);
+
+    sbvc.checkout(1, true)?;
+    assert!(!sbvc.is_changed()?);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn durable() -> SbvcResult<()> {
+    const PATH: &str = "durable.nelf";
+    const FILE: &str = "durable";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    assert!(!sbvc.durable());
+    sbvc.set_durable(true);
+    assert!(sbvc.durable());
+
+    fs::write(FILE, DATA)?;
+    sbvc.commit()?;
+    sbvc.rename("durable")?;
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    assert!(!sbvc.durable());
+    assert_eq!(sbvc.current().name(), "durable");
+    fs::write(FILE, b"")?;
+    sbvc.checkout(1, true)?;
+    assert_eq!(fs::read(FILE)?, DATA);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn path_round_trip() -> SbvcResult<()> {
+    const PATH: &str = "path_round_trip.nelf";
+    const FILE: &str = "path round trip ünïcödé";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.file(), Path::new(FILE));
+    assert_eq!(sbvc.current().source_path(), Some(Path::new(FILE)));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn path_round_trip_non_utf8() -> SbvcResult<()> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::PathBuf};
+
+    const PATH: &str = "path_round_trip_non_utf8.nelf";
+
+    let file = PathBuf::from(OsStr::from_bytes(b"path_round_trip_\xFF"));
+
+    let mut sbvc = Sbvc::new(PATH.into(), file.clone())?;
+    fs::write(&file, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.file(), file);
+    assert_eq!(sbvc.current().source_path(), Some(file.as_path()));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(file)?;
+
+    Ok(())
+}
+
+#[test]
+fn versions_on_branch() -> SbvcResult<()> {
+    const PATH: &str = "versions_on_branch.nelf";
+    const FILE: &str = "versions_on_branch";
+
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.commit()?;
+    sbvc.commit()?;
+    sbvc.commit()?;
+    sbvc.checkout(1, false)?;
+    sbvc.commit()?;
+    sbvc.commit()?;
+
+    let ids = |tip| -> SbvcResult<Vec<u32>> {
+        Ok(sbvc
+            .versions_on_branch(tip)?
+            .iter()
+            .map(|version| version.id())
+            .collect())
+    };
+    assert_eq!(ids(3)?, [0, 1, 2, 3]);
+    assert_eq!(ids(5)?, [0, 1, 4, 5]);
+    assert_eq!(ids(0)?, [0]);
+    assert!(matches!(ids(6), Err(SbvcError::VersionNotFound(6))));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn atomic_write() -> SbvcResult<()> {
+    const PATH: &str = "atomic_write.nelf";
+    const TEMPORARY: &str = "atomic_write.nelf.tmp";
+    const FILE: &str = "atomic_write";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    assert!(fs::metadata(TEMPORARY).is_err());
+
+    for index in 0..64 {
+        fs::write(FILE, vec![b'A' + (index % 26) as u8; 256])?;
+        sbvc.commit()?;
+        assert!(fs::metadata(TEMPORARY).is_err());
+    }
+
+    sbvc.set_durable(true);
+    sbvc.rename("last")?;
+    assert!(fs::metadata(TEMPORARY).is_err());
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.versions().len(), 65);
+    assert_eq!(sbvc.current().name(), "last");
+    Sbvc::verify_file(Path::new(PATH))?;
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn delete_version() -> SbvcResult<()> {
+    const PATH: &str = "delete_version.nelf";
+    const FILE: &str = "delete_version";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"FIRST")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"SECOND")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"THIRD")?;
+    sbvc.commit()?;
+    sbvc.checkout(1, true)?;
+    fs::write(FILE, b"FOURTH")?;
+    sbvc.commit()?;
+
+    sbvc.delete_version(2, false)?;
+    assert_eq!(sbvc.versions().len(), 3);
+    assert_eq!(sbvc.current().id(), 4);
+    assert_eq!(fs::read(FILE)?, b"FOURTH");
+
+    assert!(matches!(
+        sbvc.delete_version(1, false),
+        Err(SbvcError::WouldOrphanCurrent(1))
+    ));
+    assert!(matches!(
+        sbvc.delete_version(4, false),
+        Err(SbvcError::WouldOrphanCurrent(4))
+    ));
+    sbvc.delete_version(0, false)?;
+    assert_eq!(sbvc.versions().len(), 3);
+
+    sbvc.delete_version(1, true)?;
+    assert_eq!(sbvc.versions().len(), 1);
+    assert_eq!(sbvc.current().id(), 0);
+    assert_eq!(fs::read(FILE)?, b"");
+    assert!(sbvc.delete_version(1, true).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn long_history() -> SbvcResult<()> {
+    const PATH: &str = "long_history.nelf";
+    const FILE: &str = "long_history";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    let mut contents = vec![Vec::new()];
+
+    for index in 0..200 {
+        let content = format!("LINE {}\n", index % 7).repeat(index % 13 + 1);
+        fs::write(FILE, &content)?;
+        sbvc.commit()?;
+        contents.push(content.into_bytes());
+    }
+
+    sbvc.verify_reconstruction(200)?;
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    for id in [200, 1, 100, 199, 0, 137] {
+        sbvc.checkout(id, true)?;
+        assert_eq!(fs::read(FILE)?, contents[id as usize]);
+        assert_eq!(sbvc.content_hash(id)?, sbvc.working_hash()?);
+    }
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn content_slice() -> SbvcResult<()> {
+    const PATH: &str = "content_slice.nelf";
+    const FILE: &str = "content_slice";
+
+    let content: Vec<_> = (0..1 << 16).map(|index| index as u8).collect();
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, &content)?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.content_slice(1, 1000..2024)?, &content[1000..2024]);
+    assert_eq!(sbvc.content_slice(1, 65000..70000)?, &content[65000..]);
+    assert!(sbvc.content_slice(1, 70000..80000)?.is_empty());
+    assert!(sbvc.content_slice(0, 0..10)?.is_empty());
+    assert!(sbvc.content_slice(2, 0..10).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn deep_chain() -> SbvcResult<()> {
+    const PATH_1: &str = "deep_chain_1.nelf";
+    const PATH_2: &str = "deep_chain_2.nelf";
+    const FILE: &str = "deep_chain";
+    const DIR: &str = "deep_chain_patches";
+    const DEPTH: u32 = 10_000;
+
+    let _ = fs::remove_dir_all(DIR);
+
+    let mut sbvc = Sbvc::new(PATH_1.into(), FILE.into())?;
+    fs::write(FILE, b"A")?;
+    sbvc.commit()?;
+
+    // Every version of the chain inserts one byte at the start of its base
+    let paths = sbvc.export_patch_series(0, 1, Path::new(DIR))?;
+    let patch = fs::read(&paths[0])?;
+    fs::remove_file(&paths[0])?;
+
+    for number in 0..DEPTH {
+        fs::write(Path::new(DIR).join(format!("{:05}.patch", number)), &patch)?;
+    }
+
+    let mut sbvc = Sbvc::new(PATH_2.into(), FILE.into())?;
+    sbvc.apply_patch_series(Path::new(DIR))?;
+
+    sbvc.checkout(DEPTH, true)?;
+    assert_eq!(fs::read(FILE)?, vec![b'A'; DEPTH as usize]);
+
+    sbvc.delete()?;
+    assert_eq!(sbvc.current().id(), DEPTH - 1);
+    assert_eq!(fs::read(FILE)?, vec![b'A'; DEPTH as usize - 1]);
+
+    sbvc.delete_version(2, true)?;
+    assert_eq!(sbvc.versions().len(), 2);
+    assert_eq!(sbvc.current().id(), 1);
+    assert_eq!(fs::read(FILE)?, b"A");
+
+    fs::remove_dir_all(DIR)?;
+    fs::remove_file(PATH_1)?;
+    fs::remove_file(PATH_2)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn open_lenient_warnings() -> SbvcResult<()> {
+    const PATH: &str = "open_lenient_warnings.nelf";
+    const FILE: &str = "open_lenient_warnings";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+
+    let source = fs::read(PATH)?;
+    let mut corrupted = Vec::new();
+    corrupted
+        .extend_from_slice(source.split(|&ch| ch == b'\n').next().unwrap());
+    corrupted.extend_from_slice(b"\n|5|\n|0|\n");
+    fs::write(PATH, corrupted)?;
+
+    assert!(Sbvc::open(PATH.into()).is_err());
+
+    let (sbvc, warnings) = Sbvc::open_lenient(PATH.into())?;
+    assert_eq!(sbvc.current().id(), 0);
+    assert_eq!(
+        warnings,
+        [
+            Warning::VersionsMissing,
+            Warning::CurrentReset(5),
+            Warning::NextFixed(0, 1),
+        ]
+    );
+    assert!(warnings[1].to_string().contains('5'));
+
+    let (_, warnings) = Sbvc::open_lenient(PATH.into())?;
+    assert_eq!(warnings.len(), 3);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn commit_with_name() -> SbvcResult<()> {
+    const PATH: &str = "commit_with_name.nelf";
+    const FILE: &str = "commit_with_name";
+    const NAME: &str = "new name";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"SOME OTHER DATA")?;
+    sbvc.commit_with_name(NAME)?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.versions()[1].name(), "unnamed");
+    assert_eq!(sbvc.current().name(), NAME);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn total_sizes() -> SbvcResult<()> {
+    const PATH: &str = "total_sizes.nelf";
+    const FILE: &str = "total_sizes";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    assert_eq!(sbvc.total_inserted_bytes(), 0);
+    assert_eq!(sbvc.total_deleted_spans(), 0);
+
+    fs::write(FILE, b"AAAA")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"AAAABBBBBBBB")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"AABBBBBBBB")?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.total_inserted_bytes(), 4 + 8);
+    assert_eq!(sbvc.total_deleted_spans(), 2);
+    assert_eq!(
+        sbvc.total_inserted_bytes() + sbvc.total_deleted_spans(),
+        sbvc.versions().iter().map(|version| version.stored_size()).sum()
+    );
+
+    let sbvc = Sbvc::open_lazy(PATH.into())?;
+    assert_eq!(sbvc.total_inserted_bytes(), 4 + 8);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn content() -> SbvcResult<()> {
+    const PATH: &str = "content.nelf";
+    const FILE: &str = "content";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+    const DATA_3: &[u8] = b"UNCOMMITTED DATA";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_3)?;
+
+    assert_eq!(sbvc.content(0)?, b"");
+    assert_eq!(sbvc.content(1)?, DATA_1);
+    assert_eq!(sbvc.content(2)?, DATA_2);
+    assert!(matches!(sbvc.content(3), Err(SbvcError::VersionNotFound(3))));
+    assert_eq!(fs::read(FILE)?, DATA_3);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn pre_commit_hook() -> SbvcResult<()> {
+    const PATH: &str = "pre_commit_hook.nelf";
+    const FILE: &str = "pre_commit_hook";
+    const DATA: &[u8] = b"SOME DATA TO PUT INTO FILE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    sbvc.set_pre_commit_hook(|content| {
+        if content.is_empty() {
+            Err("Empty content".to_string())
+        } else {
+            Ok(())
+        }
+    });
+
+    fs::write(FILE, b"")?;
+    assert!(matches!(
+        sbvc.commit(),
+        Err(SbvcError::HookRejected(error)) if error == "Empty content"
+    ));
+    assert!(sbvc.commit_from(&b""[..]).is_err());
+    assert_eq!(sbvc.versions().len(), 1);
+    assert_eq!(Sbvc::open(PATH.into())?.versions().len(), 1);
+
+    fs::write(FILE, DATA)?;
+    sbvc.commit()?;
+    assert_eq!(sbvc.current().id(), 1);
+
+    sbvc.clear_pre_commit_hook();
+    fs::write(FILE, b"")?;
+    sbvc.commit()?;
+    assert_eq!(sbvc.current().id(), 2);
+
+    sbvc.set_pre_commit_hook(|content| {
+        if content.is_empty() {
+            Err("Empty content".to_string())
+        } else {
+            Ok(())
+        }
+    });
+    assert!(sbvc.relocate("pre_commit_hook_moved".into(), true).is_err());
+    assert_eq!(sbvc.versions().len(), 3);
+    assert_eq!(sbvc.file(), Path::new(FILE));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn description() -> SbvcResult<()> {
+    const PATH: &str = "description.nelf";
+    const FILE: &str = "description";
+    const MESSAGE: &str =
+        "Short summary\n\n|Longer| explanation\\/\nof the change\n";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    assert_eq!(sbvc.current().description(), "");
+
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit_with_message(MESSAGE)?;
+    fs::write(FILE, b"SOME OTHER DATA")?;
+    sbvc.commit()?;
+    sbvc.set_description("Another description")?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.versions()[0].description(), "");
+    assert_eq!(sbvc.versions()[1].description(), MESSAGE);
+    assert_eq!(sbvc.versions()[1].name(), "unnamed");
+    assert_eq!(sbvc.current().description(), "Another description");
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn find_by_message() -> SbvcResult<()> {
+    const PATH: &str = "find_by_message.nelf";
+    const FILE: &str = "find_by_message";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"FIRST")?;
+    sbvc.commit_with_message("Fix the parser")?;
+    fs::write(FILE, b"SECOND")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"THIRD")?;
+    sbvc.commit_with_message("Parser: fix escaping\n\nDetails")?;
+
+    assert_eq!(sbvc.find_by_message("fix", false), [3]);
+    assert_eq!(sbvc.find_by_message("Parser", false), [3]);
+    assert_eq!(sbvc.find_by_message("PARSER", true), [1, 3]);
+    assert_eq!(sbvc.find_by_message("", false), [1, 3]);
+    assert!(sbvc.find_by_message("missing", true).is_empty());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn equals_version() -> SbvcResult<()> {
+    const PATH_1: &str = "equals_version_1.nelf";
+    const PATH_2: &str = "equals_version_2.nelf";
+    const FILE_1: &str = "equals_version_1";
+    const FILE_2: &str = "equals_version_2";
+
+    let mut sbvc_1 = Sbvc::new(PATH_1.into(), FILE_1.into())?;
+    fs::write(FILE_1, b"AAAA")?;
+    sbvc_1.commit()?;
+    fs::write(FILE_1, b"AAAABBBB")?;
+    sbvc_1.commit()?;
+
+    let mut sbvc_2 = Sbvc::new(PATH_2.into(), FILE_2.into())?;
+    fs::write(FILE_2, b"AAAABBBB")?;
+    sbvc_2.commit()?;
+
+    assert!(sbvc_1.equals_version(2, &sbvc_2, 1)?);
+    assert!(sbvc_2.equals_version(1, &sbvc_1, 2)?);
+    assert!(!sbvc_1.equals_version(1, &sbvc_2, 1)?);
+    assert!(sbvc_1.equals_version(0, &sbvc_2, 0)?);
+    assert!(sbvc_1.equals_version(1, &sbvc_2, 2).is_err());
+
+    fs::remove_file(PATH_1)?;
+    fs::remove_file(PATH_2)?;
+    fs::remove_file(FILE_1)?;
+    fs::remove_file(FILE_2)?;
+
+    Ok(())
+}
+
+#[test]
+fn checkout_by_name() -> SbvcResult<()> {
+    const PATH: &str = "checkout_by_name.nelf";
+    const FILE: &str = "checkout_by_name";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit_with_name("first")?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit_with_name("second")?;
+    sbvc.commit_with_name("first")?;
+
+    assert_eq!(
+        sbvc.version_by_name("second").map(|version| version.id()),
+        Some(2)
+    );
+    assert_eq!(
+        sbvc.version_by_name("first").map(|version| version.id()),
+        Some(3)
+    );
+    assert!(sbvc.version_by_name("third").is_none());
+
+    sbvc.checkout_by_name("second", false)?;
+    assert_eq!(sbvc.current().id(), 2);
+
+    sbvc.checkout_by_name("init", true)?;
+    assert_eq!(sbvc.current().id(), 0);
+    assert_eq!(fs::read(FILE)?, b"");
+
+    assert!(matches!(
+        sbvc.checkout_by_name("third", true),
+        Err(SbvcError::NameNotFound(name)) if name == "third"
+    ));
+    assert_eq!(sbvc.current().id(), 0);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn open_large_tree() -> SbvcResult<()> {
+    const PATH: &str = "open_large_tree.nelf";
+    const FILE: &str = "open_large_tree";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+
+    for index in 0..50 {
+        let content: Vec<_> =
+            (0..1024).map(|byte| (byte * index % 251) as u8).collect();
+        fs::write(FILE, content)?;
+        sbvc.commit()?;
+    }
+
+    let eager = Sbvc::open(PATH.into())?;
+    let lazy = Sbvc::open_lazy(PATH.into())?;
+
+    for version in sbvc.versions() {
+        let id = version.id();
+        assert_eq!(eager.content(id)?, sbvc.content(id)?);
+        assert_eq!(lazy.content(id)?, sbvc.content(id)?);
+    }
+
+    assert_eq!(eager.total_inserted_bytes(), sbvc.total_inserted_bytes());
+    assert_eq!(lazy.total_inserted_bytes(), sbvc.total_inserted_bytes());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn error_source() {
+    use std::{error::Error, io};
+
+    const PATH: &str = "error_source.nelf";
+
+    let error = Sbvc::open(PATH.into()).unwrap_err();
+    let source = error.source().unwrap().downcast_ref::<io::Error>().unwrap();
+    assert_eq!(source.kind(), io::ErrorKind::NotFound);
+
+    let error = SbvcError::from("x".parse::<u32>().unwrap_err());
+    assert!(error.source().is_some());
+    assert!(SbvcError::VersionNotFound(1).source().is_none());
+    assert!(SbvcError::InvalidFormat(String::new()).source().is_none());
+}
+
+#[test]
+fn try_current() -> SbvcResult<()> {
+    const PATH: &str = "try_current.nelf";
+    const FILE: &str = "try_current";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+    assert_eq!(sbvc.try_current()?.id(), 1);
+
+    sbvc.delete()?;
+    assert_eq!(sbvc.try_current()?.id(), 0);
+
+    let source = fs::read(PATH)?;
+    let mut lines = source.splitn(3, |&ch| ch == b'\n');
+    let mut corrupted = Vec::new();
+    corrupted.extend_from_slice(lines.next().unwrap());
+    corrupted.extend_from_slice(b"\n|4000000000|\n");
+    corrupted.extend_from_slice(lines.nth(1).unwrap());
+    fs::write(PATH, corrupted)?;
+
+    assert!(matches!(
+        Sbvc::open(PATH.into()),
+        Err(SbvcError::VersionNotFound(4000000000))
+    ));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn diff_versions() -> SbvcResult<()> {
+    use wgdiff::Patch;
+
+    const PATH: &str = "diff_versions.nelf";
+    const FILE: &str = "diff_versions";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+
+    for data in [&b"BANANA"[..], b"ATANA", b"ANANAS"] {
+        fs::write(FILE, data)?;
+        sbvc.commit()?;
+    }
+
+    for (from, to) in [(1, 3), (3, 1), (2, 3), (0, 2), (2, 2)] {
+        let difference = sbvc.diff_versions(from, to)?;
+        let mut content = sbvc.content(from)?;
+        content.patch(difference.borrow());
+        assert_eq!(content, sbvc.content(to)?);
+    }
+
+    assert!(sbvc.diff_versions(2, 2)?.deletions.is_empty());
+    assert!(matches!(
+        sbvc.diff_versions(1, 4),
+        Err(SbvcError::VersionNotFound(4))
+    ));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn commit_at() -> SbvcResult<()> {
+    use std::time::SystemTime;
+
+    const PATH: &str = "commit_at.nelf";
+    const FILE: &str = "commit_at";
+    const DATA_1: &[u8] = b"SOME DATA TO PUT INTO FILE";
+    const DATA_2: &[u8] = b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE";
+
+    let when = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, DATA_1)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_2)?;
+    sbvc.commit()?;
+    fs::write(FILE, DATA_1)?;
+    assert_eq!(sbvc.commit_at(when)?, 3);
+
+    assert!(sbvc
+        .commit_at(SystemTime::UNIX_EPOCH - Duration::from_secs(1))
+        .is_err());
+    assert_eq!(sbvc.versions().len(), 4);
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.current().date(), when);
+    assert!(sbvc.current().date() < sbvc.versions()[1].date());
+    assert_eq!(sbvc.first_version_with_content(DATA_1), Some(3));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn revert() -> SbvcResult<()> {
+    const PATH: &str = "revert.nelf";
+    const FILE: &str = "revert";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+
+    for data in [&b"ABC"[..], b"ABXC", b"ABXCD"] {
+        fs::write(FILE, data)?;
+        sbvc.commit()?;
+    }
+
+    sbvc.revert(2)?;
+    assert_eq!(sbvc.current().id(), 4);
+    assert_eq!(sbvc.current().base(), 3);
+    assert_eq!(sbvc.current().name(), "revert 2");
+    assert_eq!(sbvc.content(4)?, b"ABCD");
+    assert_eq!(fs::read(FILE)?, b"ABXCD");
+
+    sbvc.revert(3)?;
+    assert_eq!(sbvc.content(5)?, b"ABC");
+
+    sbvc.checkout(2, false)?;
+    sbvc.revert(2)?;
+    assert_eq!(sbvc.content(6)?, b"ABC");
+
+    assert!(sbvc.revert(0).is_err());
+    assert!(matches!(sbvc.revert(7), Err(SbvcError::VersionNotFound(7))));
+    assert_eq!(Sbvc::open(PATH.into())?.versions().len(), 7);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn diff_between() -> SbvcResult<()> {
+    const PATH: &str = "diff_between.nelf";
+    const FILE: &str = "diff_between";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+
+    for data in [&b"AAAABBBBCCCC"[..], b"BBBBCCCCAAAA", b"BBBBXCCCC"] {
+        fs::write(FILE, data)?;
+        sbvc.commit()?;
+    }
+
+    assert_eq!(
+        sbvc.diff_between(1, 2, false)?,
+        [BlockEdit::Deletion(0..4), BlockEdit::Insertion(8..12)]
+    );
+    assert_eq!(
+        sbvc.diff_between(1, 2, true)?,
+        [BlockEdit::Move { from_range: 0..4, to_range: 8..12 }]
+    );
+    assert_eq!(
+        sbvc.diff_between(2, 3, true)?,
+        [BlockEdit::Insertion(4..5), BlockEdit::Deletion(8..12)]
+    );
+    assert!(sbvc.diff_between(3, 3, true)?.is_empty());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn squash() -> SbvcResult<()> {
+    const PATH: &str = "squash.nelf";
+    const FILE: &str = "squash";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+
+    for data in [&b"A"[..], b"AB", b"ABC", b"ABCD"] {
+        fs::write(FILE, data)?;
+        sbvc.commit()?;
+    }
+
+    assert!(matches!(sbvc.squash(3, 1), Err(SbvcError::NotAncestor(3, 1))));
+
+    sbvc.checkout(2, false)?;
+    assert!(matches!(sbvc.squash(0, 3), Err(SbvcError::WouldOrphanCurrent(2))));
+
+    sbvc.checkout(4, false)?;
+    sbvc.squash(0, 3)?;
+
+    let sbvc_reopened = Sbvc::open(PATH.into())?;
+    for sbvc in [&sbvc, &sbvc_reopened] {
+        let ids: Vec<_> =
+            sbvc.versions().iter().map(|version| version.id()).collect();
+        assert_eq!(ids, [0, 3, 4]);
+        assert_eq!(sbvc.versions()[1].base(), 0);
+        assert_eq!(sbvc.versions()[2].base(), 3);
+        assert_eq!(sbvc.content(3)?, b"ABC");
+        assert_eq!(sbvc.content(4)?, b"ABCD");
+        assert_eq!(sbvc.current().id(), 4);
+    }
+
+    fs::write(FILE, b"ABCX")?;
+    assert_eq!(sbvc.commit_onto(3)?, 5);
+    assert!(matches!(sbvc.squash(0, 4), Err(SbvcError::InvalidOperation(_))));
+    assert_eq!(sbvc.versions().len(), 4);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn scan_versions() -> SbvcResult<()> {
+    const PATH: &str = "scan_versions.nelf";
+    const FILE: &str = "scan_versions";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit_with_name("first")?;
+    fs::write(FILE, b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE")?;
+    sbvc.commit_with_name("second")?;
+
+    let source = fs::read(PATH)?;
+    let mut lines = source.splitn(4, |&ch| ch == b'\n');
+    let mut corrupted = b"|?|\n|not an ID|\n".to_vec();
+    corrupted.extend_from_slice(lines.nth(3).unwrap());
+    fs::write(PATH, &corrupted)?;
+
+    assert!(Sbvc::open(PATH.into()).is_err());
+    assert!(Sbvc::open_lenient(PATH.into()).is_err());
+
+    let versions = Sbvc::scan_versions(Path::new(PATH))?;
+    let names: Vec<_> = versions.iter().map(|version| version.name()).collect();
+    assert_eq!(names, ["init", "first", "second"]);
+    assert_eq!(versions[2].base(), 1);
+    assert_eq!(fs::read(PATH)?, corrupted);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn open_cycle() -> SbvcResult<()> {
+    const PATH: &str = "open_cycle.nelf";
+    const FILE: &str = "open_cycle";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"AB")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"ABC")?;
+    sbvc.commit()?;
+
+    let source = fs::read_to_string(PATH)?;
+    assert!(source.contains("///|1|\n|0|\n"));
+
+    fs::write(PATH, source.replacen("///|1|\n|0|\n", "///|1|\n|2|\n", 1))?;
+    assert!(matches!(
+        Sbvc::open(PATH.into()),
+        Err(SbvcError::InvalidFormat(error)) if error.contains("cycle")
+    ));
+    assert!(Sbvc::open_lenient(PATH.into()).is_err());
+    assert!(Sbvc::open_lazy(PATH.into()).is_err());
+
+    fs::write(PATH, source.replacen("///|1|\n|0|\n", "///|1|\n|7|\n", 1))?;
+    assert!(matches!(
+        Sbvc::open(PATH.into()),
+        Err(SbvcError::InvalidFormat(error)) if error.contains("not found")
+    ));
+
+    fs::write(PATH, source)?;
+    assert_eq!(Sbvc::open(PATH.into())?.content(2)?, b"ABC");
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn reconstruction_plan() -> SbvcResult<()> {
+    const PATH: &str = "reconstruction_plan.nelf";
+    const FILE: &str = "reconstruction_plan";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+
+    for data in [&b"A"[..], b"AB", b"ABC", b"ABCD"] {
+        fs::write(FILE, data)?;
+        sbvc.commit()?;
+    }
+
+    assert_eq!(sbvc.reconstruction_plan(0)?, [0]);
+    assert_eq!(sbvc.reconstruction_plan(4)?, [0, 1, 2, 3, 4]);
+    assert_eq!(sbvc.reconstruction_plan(2)?.len(), 3);
+    assert!(sbvc.reconstruction_plan(5).is_err());
+
+    fs::write(FILE, b"XXXXXXXX")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"XXXXYXXX")?;
+    sbvc.commit()?;
+    assert!(sbvc.versions()[5].is_snapshot());
+    assert_eq!(sbvc.reconstruction_plan(6)?, [5, 6]);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn write_to_read_from() -> SbvcResult<()> {
+    const PATH: &str = "write_to_read_from.nelf";
+    const FILE: &str = "write_to_read_from";
+    const OTHER_FILE: &str = "write_to_read_from_other";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit_with_name("first")?;
+    fs::write(FILE, b"SOME OTHER DATA TO REPLACE WHAT WAS BEFORE")?;
+    sbvc.commit()?;
+    sbvc.select(1)?;
+
+    let mut buffer = Vec::new();
+    sbvc.write_to(&mut buffer)?;
+    assert_eq!(buffer, fs::read(PATH)?);
+
+    let read =
+        Sbvc::read_from(Cursor::new(&buffer), PATH.into(), OTHER_FILE.into())?;
+    assert_eq!(read.file(), Path::new(OTHER_FILE));
+    assert_eq!(read.current().id(), 1);
+    assert_eq!(read.versions().len(), sbvc.versions().len());
+
+    for (version, other) in read.versions().iter().zip(sbvc.versions()) {
+        assert_eq!(version.id(), other.id());
+        assert_eq!(version.base(), other.base());
+        assert_eq!(version.name(), other.name());
+        assert_eq!(read.content(version.id())?, sbvc.content(other.id())?);
+    }
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn chunked_difference() -> SbvcResult<()> {
+    const PATH: &str = "chunked_difference.nelf";
+    const FILE: &str = "chunked_difference";
+
+    let mut state = 1u32;
+    let mut content: Vec<_> = (0..1 << 20)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, &content)?;
+    sbvc.commit()?;
+
+    content.splice(500_000..500_004, *b"EDITED IN THE MIDDLE");
+    content[900_000] ^= 1;
+    fs::write(FILE, &content)?;
+    sbvc.commit()?;
+
+    let stored_size = sbvc.current().stored_size();
+    assert!(!sbvc.current().is_snapshot());
+    assert!(stored_size < 1 << 16, "{} bytes stored", stored_size);
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert!(sbvc.content(2)? == content);
+    sbvc.verify_reconstruction(2)?;
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compression() -> SbvcResult<()> {
+    const PATH: &str = "compression.nelf";
+    const FILE: &str = "compression";
+
+    let content = "SOME VERY REPETITIVE LINE OF TEXT\n".repeat(200);
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, &content)?;
+    sbvc.commit()?;
+
+    let mut plain = Vec::new();
+    sbvc.write_to(&mut plain)?;
+
+    assert!(!sbvc.compression());
+    sbvc.set_compression(true);
+    assert!(sbvc.compression());
+
+    let mut compressed = Vec::new();
+    sbvc.write_to(&mut compressed)?;
+    assert!(compressed.len() * 4 < plain.len());
+
+    fs::write(FILE, content.replacen("LINE", "ROW", 1))?;
+    sbvc.commit()?;
+
+    for sbvc in [Sbvc::open(PATH.into())?, Sbvc::open_lazy(PATH.into())?] {
+        assert!(sbvc.compression());
+        assert_eq!(sbvc.content(1)?, content.as_bytes());
+        assert_eq!(sbvc.content(2)?, fs::read(FILE)?);
+    }
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    sbvc.set_compression(false);
+    sbvc.select(1)?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert!(!sbvc.compression());
+    assert_eq!(sbvc.content(2)?, content.replacen("LINE", "ROW", 1).as_bytes());
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    sbvc.set_compression(true);
+    sbvc.set_deduplication(true);
+    sbvc.select(2)?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert!(sbvc.compression() && sbvc.deduplication());
+    assert_eq!(sbvc.content(1)?, content.as_bytes());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn content_preview() -> SbvcResult<()> {
+    const PATH: &str = "content_preview.nelf";
+    const FILE: &str = "content_preview";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.content_preview(1, 9)?, b"SOME DATA");
+    assert_eq!(sbvc.content_preview(1, 100)?, b"SOME DATA TO PUT INTO FILE");
+    assert!(sbvc.content_preview(0, 10)?.is_empty());
+    assert!(sbvc.content_preview(2, 10).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn verify() -> SbvcResult<()> {
+    const PATH: &str = "verify.nelf";
+    const FILE: &str = "verify";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"SOME OTHER DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+
+    assert!(!sbvc.versions()[0].has_checksum());
+    assert!(sbvc.versions()[1].has_checksum());
+    assert!(sbvc.verify(0)?);
+    assert!(sbvc.verify(1)?);
+    assert!(sbvc.verify(2)?);
+    assert!(sbvc.verify(3).is_err());
+
+    let tampered =
+        str::from_utf8(&fs::read(PATH)?).unwrap().replace("OTHER", "OTXER");
+    fs::write(PATH, tampered)?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert!(sbvc.verify(1)?);
+    assert!(!sbvc.verify(2)?);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn index() -> SbvcResult<()> {
+    const PATH: &str = "index.nelf";
+    const INDEX: &str = "index.nelf.idx";
+    const FILE: &str = "index";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    assert!(!sbvc.indexed());
+    fs::write(FILE, b"SOME DATA TO PUT INTO FILE")?;
+    sbvc.commit_with_name("first")?;
+    sbvc.set_indexed(true)?;
+    assert!(Path::new(INDEX).exists());
+    fs::write(FILE, b"SOME OTHER DATA TO PUT INTO FILE")?;
+    sbvc.commit()?;
+
+    let assert_same = |sbvc: &Sbvc| -> SbvcResult<()> {
+        let plain = Sbvc::read_from(
+            Cursor::new(fs::read(PATH)?),
+            PATH.into(),
+            FILE.into(),
+        )?;
+        assert_eq!(sbvc.current().id(), plain.current().id());
+        assert_eq!(sbvc.versions().len(), plain.versions().len());
+
+        for (version, other) in sbvc.versions().iter().zip(plain.versions()) {
+            assert_eq!(version.id(), other.id());
+            assert_eq!(version.base(), other.base());
+            assert_eq!(version.name(), other.name());
+            assert_eq!(sbvc.content(version.id())?, plain.content(other.id())?);
+        }
+
+        Ok(())
+    };
+
+    for sbvc in [Sbvc::open(PATH.into())?, Sbvc::open_lazy(PATH.into())?] {
+        assert!(sbvc.indexed());
+        assert_same(&sbvc)?;
+    }
+
+    let stale = fs::read(INDEX)?;
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    fs::write(FILE, b"DATA")?;
+    sbvc.commit()?;
+    assert_ne!(fs::read(INDEX)?, stale);
+    fs::write(INDEX, stale)?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.versions().len(), 4);
+    assert_same(&sbvc)?;
+
+    fs::write(INDEX, b"NOT AN INDEX")?;
+    assert_same(&Sbvc::open(PATH.into())?)?;
+
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    sbvc.set_indexed(false)?;
+    assert!(!Path::new(INDEX).exists());
+    sbvc.set_indexed(false)?;
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn merge() -> SbvcResult<()> {
+    const PATH: &str = "merge.nelf";
+    const FILE: &str = "merge";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"AAAA BBBB CCCC")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"AAAA YYYY CCCC")?;
+    sbvc.commit()?;
+    sbvc.select(1)?;
+    fs::write(FILE, b"AAAA XXXX CCCC DDDD")?;
+    sbvc.commit()?;
+
+    let mut session = sbvc.merge(2)?;
+    assert_eq!(session.conflicts().len(), 1);
+
+    let conflict = session.conflicts()[0].clone();
+    assert_eq!(conflict.base_range(), 5..9);
+    assert_eq!(conflict.ours(), b"XXXX");
+    assert_eq!(conflict.theirs(), b"YYYY");
+    assert!(!session.is_resolved());
+    assert!(matches!(
+        session.finalize(),
+        Err(SbvcError::MergeConflict(range)) if range == (5..9)
+    ));
+    assert!(session.resolve(0..1, Resolution::Ours).is_err());
+
+    session.resolve(conflict.base_range(), Resolution::Theirs)?;
+    assert!(session.is_resolved());
+    let id = session.finalize()?;
+    assert!(session.finalize().is_err());
+
+    assert_eq!(sbvc.current().id(), id);
+    assert_eq!(sbvc.current().base(), 3);
+    assert_eq!(sbvc.current().extra_parents(), [2]);
+    assert_eq!(sbvc.content(id)?, b"AAAA YYYY CCCC DDDD");
+
+    let mut session = sbvc.merge(2)?;
+    assert!(session.conflicts().is_empty());
+    let id = session.finalize()?;
+    assert_eq!(sbvc.content(id)?, b"AAAA YYYY CCCC DDDD");
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.versions().len(), 6);
+    assert_eq!(sbvc.versions()[5].extra_parents(), [2]);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn ancestry() -> SbvcResult<()> {
+    const PATH: &str = "ancestry.nelf";
+    const FILE: &str = "ancestry";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"SOME OTHER DATA")?;
+    sbvc.commit()?;
+    sbvc.select(1)?;
+    fs::write(FILE, b"SOME DIFFERENT DATA")?;
+    sbvc.commit()?;
+
+    let ids = |id| -> SbvcResult<Vec<u32>> {
+        Ok(sbvc.ancestry(id)?.iter().map(|version| version.id()).collect())
+    };
+    assert_eq!(ids(3)?, [3, 1, 0]);
+    assert_eq!(ids(2)?, [2, 1, 0]);
+    assert_eq!(ids(0)?, [0]);
+    assert!(matches!(sbvc.ancestry(4), Err(SbvcError::VersionNotFound(4))));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn deduplication() -> SbvcResult<()> {
+    const PATH: &str = "deduplication.nelf";
+    const FILE: &str = "deduplication";
+
+    let content = "SOME DATA THAT IS INSERTED BY SEVERAL VERSIONS\n".repeat(10);
+    let count = |source: &[u8]| {
+        source
+            .windows(content.len())
+            .filter(|window| *window == content.as_bytes())
+            .count()
+    };
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    assert!(!sbvc.deduplication());
+    fs::write(FILE, &content)?;
+    sbvc.commit()?;
+    sbvc.select(0)?;
+    fs::write(FILE, &content)?;
+    sbvc.commit()?;
+    fs::write(FILE, format!("{}MORE DATA", content))?;
+    sbvc.commit()?;
+    assert_eq!(count(&fs::read(PATH)?), 2);
+
+    sbvc.set_deduplication(true);
+    sbvc.set_indexed(true)?;
+    assert_eq!(count(&fs::read(PATH)?), 1);
+    Sbvc::verify_file(Path::new(PATH))?;
+
+    for sbvc in [Sbvc::open(PATH.into())?, Sbvc::open_lazy(PATH.into())?] {
+        assert!(sbvc.deduplication());
+        assert_eq!(sbvc.content(1)?, content.as_bytes());
+        assert_eq!(sbvc.content(2)?, content.as_bytes());
+        assert_eq!(
+            sbvc.content(3)?,
+            format!("{}MORE DATA", content).as_bytes()
+        );
+    }
+
+    let versions = Sbvc::scan_versions(Path::new(PATH))?;
+    assert_eq!(versions[1].stored_size(), content.len());
+
+    fs::remove_file(format!("{}.idx", PATH))?;
+    let mut sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.content(2)?, content.as_bytes());
+    sbvc.set_deduplication(false);
+    sbvc.select(1)?;
+    assert_eq!(count(&fs::read(PATH)?), 2);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn children_leaves() -> SbvcResult<()> {
+    const PATH: &str = "children_leaves.nelf";
+    const FILE: &str = "children_leaves";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    let ids = |versions: Vec<&Version>| -> Vec<u32> {
+        versions.iter().map(|version| version.id()).collect()
+    };
+    assert_eq!(ids(sbvc.leaves()), [0]);
+
+    fs::write(FILE, b"SOME DATA")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"SOME OTHER DATA")?;
+    sbvc.commit()?;
+    sbvc.select(1)?;
+    fs::write(FILE, b"SOME DIFFERENT DATA")?;
+    sbvc.commit()?;
+
+    assert_eq!(ids(sbvc.children(0)?), [1]);
+    assert_eq!(ids(sbvc.children(1)?), [2, 3]);
+    assert!(sbvc.children(2)?.is_empty());
+    assert!(matches!(sbvc.children(4), Err(SbvcError::VersionNotFound(4))));
+    assert_eq!(ids(sbvc.leaves()), [2, 3]);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn common_ancestor() -> SbvcResult<()> {
+    const PATH: &str = "common_ancestor.nelf";
+    const FILE: &str = "common_ancestor";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"SOME OTHER DATA")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"SOME OTHER DATA AGAIN")?;
+    sbvc.commit()?;
+    sbvc.select(1)?;
+    fs::write(FILE, b"SOME DIFFERENT DATA")?;
+    sbvc.commit()?;
+
+    assert_eq!(sbvc.common_ancestor(3, 4)?, 1);
+    assert_eq!(sbvc.common_ancestor(4, 3)?, 1);
+    assert_eq!(sbvc.common_ancestor(2, 3)?, 2);
+    assert_eq!(sbvc.common_ancestor(4, 4)?, 4);
+    assert_eq!(sbvc.common_ancestor(0, 3)?, 0);
+    assert!(matches!(
+        sbvc.common_ancestor(3, 5),
+        Err(SbvcError::VersionNotFound(5))
+    ));
+    assert!(matches!(
+        sbvc.common_ancestor(5, 3),
+        Err(SbvcError::VersionNotFound(5))
+    ));
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn open_out_of_bounds() -> SbvcResult<()> {
+    const PATH: &str = "open_out_of_bounds.nelf";
+    const FILE: &str = "open_out_of_bounds";
+
+    let content: String =
+        (0..100).map(|index| format!("{:04}", index)).collect();
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, &content)?;
+    sbvc.commit()?;
+    fs::write(FILE, format!("{}{}", &content[..200], &content[240..]))?;
+    sbvc.commit()?;
+
+    let deletion = sbvc.versions()[2].difference().deletions[0].clone();
+    let cell = format!("|{}|\n|{}|\n", deletion.start, deletion.end);
+    let source = fs::read_to_string(PATH)?;
+    assert_eq!(source.matches(&cell).count(), 1);
+
+    let end = deletion.end + 1000;
+    fs::write(
+        PATH,
+        source.replace(&cell, &format!("|{}|\n|{}|\n", deletion.start, end)),
+    )?;
+
+    for result in [
+        Sbvc::open(PATH.into()),
+        Sbvc::open_lazy(PATH.into()),
+        Sbvc::open_lenient(PATH.into()).map(|(sbvc, _)| sbvc),
+    ] {
+        assert!(matches!(
+            result,
+            Err(SbvcError::InvalidFormat(error))
+                if error.contains("ID 2") && error.contains(&end.to_string())
+        ));
+    }
+
+    fs::write(PATH, source)?;
+    assert_eq!(Sbvc::open_lazy(PATH.into())?.content(2)?, fs::read(FILE)?);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn merge_clean() -> SbvcResult<()> {
+    const PATH: &str = "merge_clean.nelf";
+    const FILE: &str = "merge_clean";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"AAAA BBBB CCCC")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"AAAA BBBB CCCC DDDD")?;
+    sbvc.commit()?;
+    sbvc.select(1)?;
+    fs::write(FILE, b"EEEE AAAA BBBB CCCC")?;
+    sbvc.commit()?;
+
+    sbvc.merge_clean(2)?;
+    assert_eq!(sbvc.current().id(), 4);
+    assert_eq!(sbvc.current().base(), 3);
+    assert_eq!(sbvc.current().extra_parents(), [2]);
+    assert_eq!(sbvc.content(4)?, b"EEEE AAAA BBBB CCCC DDDD");
+
+    sbvc.select(1)?;
+    fs::write(FILE, b"AAAA XXXX CCCC")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"AAAA YYYY CCCC")?;
+    sbvc.commit_onto(1)?;
+
+    assert!(matches!(
+        sbvc.merge_clean(5),
+        Err(SbvcError::MergeConflict(range)) if range == (5..9)
+    ));
+    assert_eq!(sbvc.current().id(), 6);
+    assert_eq!(sbvc.versions().len(), 7);
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[test]
+fn newline_in_name() -> SbvcResult<()> {
+    const PATH: &str = "newline_in_name.nelf";
+    const FILE: &str = "newline_in_name";
+    const NAME: &str = "first line\n|second| line\n/\\";
+    const DESCRIPTION: &str = "\n||\nsome description\n\\\\//\n";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"SOME DATA")?;
+    sbvc.commit_with_name(NAME)?;
+    fs::write(FILE, b"SOME OTHER DATA")?;
+    sbvc.commit()?;
+    sbvc.rename(NAME)?;
+    sbvc.set_description(DESCRIPTION)?;
+
+    let sbvc = Sbvc::open(PATH.into())?;
+    assert_eq!(sbvc.versions().len(), 3);
+    assert_eq!(sbvc.versions()[1].name(), NAME);
+    assert_eq!(sbvc.versions()[2].name(), NAME);
+    assert_eq!(sbvc.versions()[2].description(), DESCRIPTION);
+    assert_eq!(sbvc.content(2)?, b"SOME OTHER DATA");
+    Sbvc::verify_file(Path::new(PATH))?;
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "compression"))]
+#[test]
+fn compression_unsupported() -> SbvcResult<()> {
+    const PATH: &str = "compression_unsupported.nelf";
+    const FILE: &str = "compression_unsupported";
+
+    Sbvc::new(PATH.into(), FILE.into())?;
+
+    // The header of a version tree saved with compression on has an extra
+    // cell set to `1`
+    let mut source = fs::read(PATH)?;
+    source.extend_from_slice(b"|1|\n");
+    fs::write(PATH, source)?;
+
+    assert!(matches!(
+        Sbvc::open(PATH.into()),
+        Err(SbvcError::InvalidFormat(error)) if error.contains("compression")
+    ));
+    assert!(Sbvc::open_lazy(PATH.into()).is_err());
+
+    fs::remove_file(PATH)?;
+
+    Ok(())
+}
+
+#[test]
+fn open_max_id() -> SbvcResult<()> {
+    const PATH: &str = "open_max_id.nelf";
+    const FILE: &str = "open_max_id";
+
+    let mut sbvc = Sbvc::new(PATH.into(), FILE.into())?;
+    fs::write(FILE, b"AB")?;
+    sbvc.commit()?;
+    fs::write(FILE, b"ABC")?;
+    sbvc.commit()?;
+    sbvc.select(1)?;
+
+    let source = fs::read_to_string(PATH)?;
+    assert!(source.contains("///|2|\n|1|\n"));
+
+    fs::write(
+        PATH,
+        source.replacen("///|2|\n|1|\n", "///|4294967295|\n|1|\n", 1),
+    )?;
+    assert!(matches!(
+        Sbvc::open(PATH.into()),
+        Err(SbvcError::InvalidFormat(error)) if error.contains("4294967295")
+    ));
+    assert!(Sbvc::open_lenient(PATH.into()).is_err());
+
+    fs::remove_file(PATH)?;
+    fs::remove_file(FILE)?;
+
+    Ok(())
+}